@@ -0,0 +1,313 @@
+//! Constant time hex, base64, base64url and base32 encoding and decoding of secure types.
+//!
+//! Common implementations look up each character in a table indexed by the secret data, which leaks the data through
+//! the cache. Here, characters are converted with arithmetic on masks instead, following libsodium's `sodium_bin2hex`
+//! and `sodium_bin2base64`. Only the length of the data influences the control flow and memory accesses.
+//!
+//! Decoding writes directly into locked memory, and encoding returns a [`SecureString`].
+
+use core::fmt;
+
+use allocator_api2::{alloc::Allocator, vec::Vec};
+use zeroize::Zeroize;
+
+use crate::{SecureBytes, SecureString, SecureVec};
+
+/// The reason why decoding failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DecodeError {
+    /// The length of the input is not possible in the encoding.
+    InvalidLength,
+    /// The input contains characters outside of the alphabet of the encoding, or non-zero trailing bits.
+    ///
+    /// The position of the character is not reported, since finding it would not be constant time.
+    InvalidEncoding,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidLength => write!(f, "invalid length of the encoded secret"),
+            DecodeError::InvalidEncoding => write!(f, "invalid character or trailing bits in the encoded secret"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Masks that are `0xff` if the condition holds and `0x00` otherwise, computed without branches.
+mod mask {
+    pub fn lt(a: u8, b: u8) -> u8 {
+        ((a as u16).wrapping_sub(b as u16) >> 8) as u8
+    }
+
+    pub fn eq(a: u8, b: u8) -> u8 {
+        lt(a ^ b, 1)
+    }
+
+    pub fn in_range(a: u8, low: u8, high: u8) -> u8 {
+        !lt(a, low) & !lt(high, a)
+    }
+}
+
+/// An encoding with a fixed number of bits per character.
+pub(crate) struct Codec {
+    bits: u32,
+    /// Encoded secrets are padded with `=` to a multiple of this length.
+    padded_length: Option<usize>,
+    /// Converts `bits` bits into a character.
+    encode: fn(u8) -> u8,
+    /// Converts a character into `bits` bits, and a mask that is `0xff` if the character is valid.
+    decode: fn(u8) -> (u8, u8),
+}
+
+pub(crate) const HEX: Codec = Codec {
+    bits: 4,
+    padded_length: None,
+    encode: |x| {
+        // '0'..='9' for 0..10, 'a'..='f' for 10..16
+        x.wrapping_add(b'0').wrapping_add(!mask::lt(x, 10) & (b'a' - b'0' - 10))
+    },
+    decode: |c| {
+        let digit = mask::in_range(c, b'0', b'9');
+        let lower = mask::in_range(c, b'a', b'f');
+        let upper = mask::in_range(c, b'A', b'F');
+        let value = (digit & c.wrapping_sub(b'0')) | (lower & c.wrapping_sub(b'a' - 10)) | (upper & c.wrapping_sub(b'A' - 10));
+        (value, digit | lower | upper)
+    },
+};
+
+/// Encode a value of the base64 alphabet, with the given characters for 62 and 63.
+fn encode_base64(x: u8, c62: u8, c63: u8) -> u8 {
+    (mask::lt(x, 26) & x.wrapping_add(b'A'))
+        | (mask::in_range(x, 26, 51) & x.wrapping_add(b'a' - 26))
+        | (mask::in_range(x, 52, 61) & x.wrapping_sub(52 - b'0'))
+        | (mask::eq(x, 62) & c62)
+        | (mask::eq(x, 63) & c63)
+}
+
+fn decode_base64(c: u8, c62: u8, c63: u8) -> (u8, u8) {
+    let upper = mask::in_range(c, b'A', b'Z');
+    let lower = mask::in_range(c, b'a', b'z');
+    let digit = mask::in_range(c, b'0', b'9');
+    let is62 = mask::eq(c, c62);
+    let is63 = mask::eq(c, c63);
+    let value = (upper & c.wrapping_sub(b'A'))
+        | (lower & c.wrapping_sub(b'a' - 26))
+        | (digit & c.wrapping_add(52 - b'0'))
+        | (is62 & 62)
+        | (is63 & 63);
+    (value, upper | lower | digit | is62 | is63)
+}
+
+pub(crate) const BASE64: Codec = Codec {
+    bits: 6,
+    padded_length: Some(4),
+    encode: |x| encode_base64(x, b'+', b'/'),
+    decode: |c| decode_base64(c, b'+', b'/'),
+};
+
+pub(crate) const BASE64_URL: Codec = Codec {
+    bits: 6,
+    padded_length: None,
+    encode: |x| encode_base64(x, b'-', b'_'),
+    decode: |c| decode_base64(c, b'-', b'_'),
+};
+
+const BASE32: Codec = Codec {
+    bits: 5,
+    padded_length: Some(8),
+    encode: |x| (mask::lt(x, 26) & x.wrapping_add(b'A')) | (!mask::lt(x, 26) & x.wrapping_add(b'2' - 26)),
+    decode: |c| {
+        let letter = mask::in_range(c, b'A', b'Z');
+        let digit = mask::in_range(c, b'2', b'7');
+        ((letter & c.wrapping_sub(b'A')) | (digit & c.wrapping_sub(b'2' - 26)), letter | digit)
+    },
+};
+
+impl Codec {
+    pub(crate) fn encode(&self, bytes: &[u8]) -> SecureString {
+        let chars = (bytes.len() * 8).div_ceil(self.bits as usize);
+        let length = self
+            .padded_length
+            .map_or(chars, |padded_length| chars.next_multiple_of(padded_length));
+        let mut encoded = SecureBytes::new_in(Vec::with_capacity(length));
+
+        let char_mask = (1u32 << self.bits) - 1;
+        let mut buffer = 0u32;
+        let mut buffered_bits = 0;
+        for &byte in bytes {
+            buffer = (buffer << 8) | byte as u32;
+            buffered_bits += 8;
+            while buffered_bits >= self.bits {
+                buffered_bits -= self.bits;
+                encoded.push((self.encode)(((buffer >> buffered_bits) & char_mask) as u8));
+            }
+        }
+        if buffered_bits > 0 {
+            encoded.push((self.encode)(((buffer << (self.bits - buffered_bits)) & char_mask) as u8));
+        }
+        encoded.resize(length, b'=');
+        buffer.zeroize();
+
+        // Only ASCII characters were pushed
+        SecureString(encoded)
+    }
+
+    pub(crate) fn decode(&self, encoded: &[u8]) -> Result<SecureBytes, DecodeError> {
+        let mut encoded = encoded;
+        if let Some(padded_length) = self.padded_length {
+            // Padding is optional when decoding, but if present, it must pad the input to exactly the next multiple
+            let unpadded = encoded.len() - encoded.iter().rev().take_while(|&&c| c == b'=').count();
+            if unpadded < encoded.len() && encoded.len() != unpadded.next_multiple_of(padded_length) {
+                return Err(DecodeError::InvalidLength);
+            }
+            encoded = &encoded[..unpadded];
+        }
+        let mut decoded = SecureBytes::new_in(Vec::with_capacity(encoded.len() * self.bits as usize / 8));
+
+        let mut valid = 0xff;
+        let mut buffer = 0u32;
+        let mut buffered_bits = 0;
+        for &c in encoded {
+            let (value, is_valid) = (self.decode)(c);
+            valid &= is_valid;
+            buffer = (buffer << self.bits) | value as u32;
+            buffered_bits += self.bits;
+            if buffered_bits >= 8 {
+                buffered_bits -= 8;
+                decoded.push((buffer >> buffered_bits) as u8);
+            }
+        }
+        // Leftover bits are only possible if they are less than a character, and they must be zero
+        let trailing = buffer & ((1 << buffered_bits) - 1);
+        buffer.zeroize();
+
+        if buffered_bits >= self.bits {
+            return Err(DecodeError::InvalidLength);
+        }
+        if valid != 0xff || trailing != 0 {
+            return Err(DecodeError::InvalidEncoding);
+        }
+        Ok(decoded)
+    }
+}
+
+impl SecureBytes {
+    /// Decode a hex string into a new `SecureBytes` in constant time, accepting lower and upper case.
+    pub fn from_hex(encoded: impl AsRef<[u8]>) -> Result<Self, DecodeError> {
+        HEX.decode(encoded.as_ref())
+    }
+
+    /// Decode standard base64 (RFC 4648 section 4) into a new `SecureBytes` in constant time.
+    ///
+    /// The `=` padding is optional, but must be complete if present.
+    pub fn from_base64(encoded: impl AsRef<[u8]>) -> Result<Self, DecodeError> {
+        BASE64.decode(encoded.as_ref())
+    }
+
+    /// Decode unpadded URL-safe base64 (RFC 4648 section 5) into a new `SecureBytes` in constant time.
+    pub fn from_base64url(encoded: impl AsRef<[u8]>) -> Result<Self, DecodeError> {
+        BASE64_URL.decode(encoded.as_ref())
+    }
+
+    /// Decode base32 (RFC 4648 section 6) into a new `SecureBytes` in constant time.
+    ///
+    /// The `=` padding is optional, but must be complete if present.
+    pub fn from_base32(encoded: impl AsRef<[u8]>) -> Result<Self, DecodeError> {
+        BASE32.decode(encoded.as_ref())
+    }
+}
+
+impl<A: Allocator> SecureVec<u8, A> {
+    /// Encode the contents as lower case hex in constant time.
+    pub fn to_hex_secure(&self) -> SecureString {
+        HEX.encode(self.unsecure())
+    }
+
+    /// Encode the contents as padded standard base64 (RFC 4648 section 4) in constant time.
+    pub fn to_base64_secure(&self) -> SecureString {
+        BASE64.encode(self.unsecure())
+    }
+
+    /// Encode the contents as unpadded URL-safe base64 (RFC 4648 section 5) in constant time.
+    pub fn to_base64url_secure(&self) -> SecureString {
+        BASE64_URL.encode(self.unsecure())
+    }
+
+    /// Encode the contents as padded base32 (RFC 4648 section 6) in constant time.
+    pub fn to_base32_secure(&self) -> SecureString {
+        BASE32.encode(self.unsecure())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecodeError, BASE32, BASE64, BASE64_URL, HEX};
+    use crate::SecureBytes;
+
+    #[test]
+    fn test_rfc4648_vectors() {
+        let vectors = [
+            ("", "", "", ""),
+            ("f", "Zg==", "MY======", "66"),
+            ("fo", "Zm8=", "MZXQ====", "666f"),
+            ("foo", "Zm9v", "MZXW6===", "666f6f"),
+            ("foob", "Zm9vYg==", "MZXW6YQ=", "666f6f62"),
+            ("fooba", "Zm9vYmE=", "MZXW6YTB", "666f6f6261"),
+            ("foobar", "Zm9vYmFy", "MZXW6YTBOI======", "666f6f626172"),
+        ];
+        for (plain, base64, base32, hex) in vectors {
+            let bytes = SecureBytes::from(plain);
+            assert_eq!(bytes.to_base64_secure().unsecure(), base64);
+            assert_eq!(bytes.to_base32_secure().unsecure(), base32);
+            assert_eq!(bytes.to_hex_secure().unsecure(), hex);
+            assert_eq!(bytes.to_base64url_secure().unsecure(), base64.trim_end_matches('='));
+
+            assert_eq!(SecureBytes::from_base64(base64).unwrap(), bytes);
+            assert_eq!(SecureBytes::from_base64(base64.trim_end_matches('=')).unwrap(), bytes);
+            assert_eq!(SecureBytes::from_base32(base32).unwrap(), bytes);
+            assert_eq!(SecureBytes::from_hex(hex).unwrap(), bytes);
+            assert_eq!(SecureBytes::from_hex(hex.to_uppercase()).unwrap(), bytes);
+            assert_eq!(SecureBytes::from_base64url(base64.trim_end_matches('=')).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_all_bytes() {
+        let bytes = SecureBytes::new((0..=255).collect());
+        for codec in [HEX, BASE64, BASE64_URL, BASE32] {
+            let encoded = codec.encode(bytes.unsecure());
+            assert!(encoded.is_locked());
+            let decoded = codec.decode(encoded.unsecure().as_bytes()).unwrap();
+            assert!(decoded.is_locked());
+            assert_eq!(decoded, bytes);
+        }
+        let url = bytes.to_base64url_secure();
+        assert!(url.unsecure().contains('-') && url.unsecure().contains('_'));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(SecureBytes::from_hex("abc"), Err(DecodeError::InvalidLength));
+        assert_eq!(SecureBytes::from_hex("0g"), Err(DecodeError::InvalidEncoding));
+        assert_eq!(SecureBytes::from_hex("0:"), Err(DecodeError::InvalidEncoding));
+        assert_eq!(SecureBytes::from_base64("Zm9vY"), Err(DecodeError::InvalidLength));
+        assert_eq!(SecureBytes::from_base64("Zm9v-g=="), Err(DecodeError::InvalidEncoding));
+        assert_eq!(SecureBytes::from_base64url("Zm9v+g"), Err(DecodeError::InvalidEncoding));
+        assert_eq!(SecureBytes::from_base64url("Zm8="), Err(DecodeError::InvalidEncoding));
+        // Non-zero trailing bits
+        assert_eq!(SecureBytes::from_base64("Zh=="), Err(DecodeError::InvalidEncoding));
+        assert_eq!(SecureBytes::from_base32("MZ======"), Err(DecodeError::InvalidEncoding));
+        assert_eq!(SecureBytes::from_base32("M"), Err(DecodeError::InvalidLength));
+        assert_eq!(SecureBytes::from_base32("my"), Err(DecodeError::InvalidEncoding));
+        // More or less padding than needed for the last group
+        assert_eq!(SecureBytes::from_base64("Zg==="), Err(DecodeError::InvalidLength));
+        assert_eq!(SecureBytes::from_base64("Zg="), Err(DecodeError::InvalidLength));
+        assert_eq!(SecureBytes::from_base64("Zm9v===="), Err(DecodeError::InvalidLength));
+        assert_eq!(SecureBytes::from_base64("===="), Err(DecodeError::InvalidLength));
+        assert_eq!(SecureBytes::from_base32("MY======="), Err(DecodeError::InvalidLength));
+        assert_eq!(SecureBytes::from_base32("MZXW6YTB========"), Err(DecodeError::InvalidLength));
+    }
+}
@@ -3,10 +3,40 @@
 mod secure_types;
 mod secure_utils;
 
+#[cfg(feature = "encoding")]
+mod encoding;
+
+#[cfg(feature = "pem")]
+pub mod pem;
+
+#[cfg(all(feature = "prompt", unix))]
+mod prompt;
+
 #[cfg(feature = "serde")]
-mod serde;
+pub mod serde;
+
+#[cfg(feature = "subtle")]
+mod subtle;
+
+pub use secure_types::{
+    array::SecureArray,
+    boxed::SecureBox,
+    io::{ReadOptions, SecureCursor},
+    protected::{ProtectedBox, ProtectedVec, ReadGuard, WriteGuard},
+    string::SecureString,
+    vec::SecureBytes,
+    vec::SecureVec,
+};
+pub use secure_utils::{
+    allocator::{capabilities, default_backend, set_default_backend, Backend, Capabilities, SecureAllocator},
+    lock_policy::{lock_policy, set_lock_policy, LockError, LockPolicy},
+};
+
+#[cfg(feature = "encoding")]
+pub use encoding::DecodeError;
 
-pub use secure_types::{array::SecureArray, boxed::SecureBox, string::SecureString, vec::SecureBytes, vec::SecureVec};
+/// Re-exported for naming allocators and constructing values with [`SecureVec::new_in`] and [`SecureBox::new_in`].
+pub use allocator_api2;
 
 #[doc = include_str!("../README.md")]
 #[cfg(doctest)]
@@ -0,0 +1,271 @@
+//! Reading passwords from the terminal straight into a [`SecureString`], without an intermediate `String`.
+//!
+//! The terminal is switched to a raw mode without echo while the password is typed, and the input is read byte by
+//! byte into locked memory. Since the line editing of the terminal is disabled, backspace and Ctrl-U are handled here.
+//! The original terminal settings are restored when reading ends, also on errors and panics.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    os::fd::AsRawFd,
+};
+
+use zeroize::Zeroize;
+
+use crate::SecureString;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_U: u8 = 0x15;
+
+impl SecureString {
+    /// Print `prompt` to the terminal and read a password from it, without echoing the password.
+    ///
+    /// The password ends at the first newline, which is not included. Backspace removes the last character and
+    /// Ctrl-U removes all characters typed so far. Ctrl-C aborts with an error of kind
+    /// [`Interrupted`](io::ErrorKind::Interrupted).
+    ///
+    /// This reads from `/dev/tty`, such that it works even if the standard input is redirected.
+    pub fn read_password(prompt: &str) -> io::Result<SecureString> {
+        read_password_from(&open_tty()?, prompt)
+    }
+
+    /// Like [`SecureString::read_password`], but asks for the password a second time with `confirmation_prompt`.
+    ///
+    /// Returns an error of kind [`InvalidData`](io::ErrorKind::InvalidData) if the two passwords differ.
+    pub fn read_password_confirmed(prompt: &str, confirmation_prompt: &str) -> io::Result<SecureString> {
+        read_password_confirmed_from(&open_tty()?, prompt, confirmation_prompt)
+    }
+}
+
+fn open_tty() -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).open("/dev/tty")
+}
+
+fn read_password_confirmed_from(tty: &File, prompt: &str, confirmation_prompt: &str) -> io::Result<SecureString> {
+    let password = read_password_from(tty, prompt)?;
+    // Constant time comparison
+    if read_password_from(tty, confirmation_prompt)? != password {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the passwords do not match"));
+    }
+    Ok(password)
+}
+
+fn read_password_from(tty: &File, prompt: &str) -> io::Result<SecureString> {
+    let mode = RawMode::enable(tty)?;
+    // Printed in raw mode, such that nothing typed in response to it is echoed
+    let mut tty = mode.tty;
+    tty.write_all(prompt.as_bytes())?;
+    tty.flush()?;
+    let password = read_line(tty);
+    // The newline was not echoed, and the cursor should not stay behind the prompt on errors either
+    tty.write_all(b"\n")?;
+    password
+}
+
+/// Read bytes until a newline, interpreting the control characters of a terminal in raw mode.
+fn read_line(mut tty: &File) -> io::Result<SecureString> {
+    let mut password = SecureString::with_capacity(128);
+    // The bytes of a multi-byte character that is not complete yet
+    let mut pending = [0u8; 4];
+    let mut pending_len = 0;
+    let mut byte = [0u8; 1];
+
+    let result = loop {
+        match tty.read(&mut byte) {
+            Ok(0) => break Ok(()),
+            Ok(_) => {},
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => break Err(error),
+        }
+
+        match byte[0] {
+            b'\n' | b'\r' => break Ok(()),
+            CTRL_D if password.is_empty() && pending_len == 0 => {
+                break Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no password was entered"))
+            },
+            CTRL_D => break Ok(()),
+            CTRL_C => break Err(io::Error::new(io::ErrorKind::Interrupted, "reading the password was interrupted")),
+            BACKSPACE | DELETE => {
+                if pending_len == 0 {
+                    password.pop();
+                }
+                pending_len = 0;
+            },
+            CTRL_U => {
+                password.clear();
+                pending_len = 0;
+            },
+            byte => {
+                pending[pending_len] = byte;
+                pending_len += 1;
+                match std::str::from_utf8(&pending[..pending_len]) {
+                    Ok(ch) => {
+                        password.push_str(ch);
+                        pending_len = 0;
+                    },
+                    // Wait for the remaining bytes of the character
+                    Err(error) if error.error_len().is_none() => {},
+                    // Skip invalid UTF-8
+                    Err(_) => pending_len = 0,
+                }
+            },
+        }
+    };
+
+    pending.zeroize();
+    byte.zeroize();
+    result.map(|()| password)
+}
+
+/// Disables echo, line editing and signals of a terminal, until it is dropped.
+struct RawMode<'a> {
+    tty: &'a File,
+    original: libc::termios,
+}
+
+impl<'a> RawMode<'a> {
+    fn enable(tty: &'a File) -> io::Result<Self> {
+        let fd = tty.as_raw_fd();
+        let mut original = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ECHO | libc::ECHONL | libc::ICANON | libc::ISIG | libc::IEXTEN);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { tty, original })
+    }
+}
+
+impl<'a> Drop for RawMode<'a> {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.original) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::CStr,
+        fs::File,
+        io::{self, Read, Write},
+        os::fd::{AsRawFd, FromRawFd},
+        sync::Mutex,
+        thread,
+    };
+
+    use super::{read_password_confirmed_from, read_password_from, RawMode};
+    use crate::SecureString;
+
+    /// Open a pseudo-terminal, returns the controlling side and the terminal.
+    fn open_pty() -> (File, File) {
+        // `ptsname` returns a static buffer
+        static PTSNAME: Mutex<()> = Mutex::new(());
+        let _lock = PTSNAME.lock().unwrap();
+        unsafe {
+            let controller = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(controller >= 0);
+            assert_eq!(libc::grantpt(controller), 0);
+            assert_eq!(libc::unlockpt(controller), 0);
+            let name = CStr::from_ptr(libc::ptsname(controller)).to_str().unwrap().to_string();
+            let tty = std::fs::OpenOptions::new().read(true).write(true).open(name).unwrap();
+            (File::from_raw_fd(controller), tty)
+        }
+    }
+
+    /// Read from the controlling side of a pseudo-terminal until `expected` was received.
+    fn expect_output(controller: &mut File, expected: &str) {
+        let mut output = Vec::new();
+        while !String::from_utf8_lossy(&output).contains(expected) {
+            let mut buffer = [0; 64];
+            let len = controller.read(&mut buffer).unwrap();
+            output.extend_from_slice(&buffer[..len]);
+        }
+    }
+
+    fn echo_enabled(tty: &File) -> bool {
+        let mut termios = unsafe { std::mem::zeroed() };
+        assert_eq!(unsafe { libc::tcgetattr(tty.as_raw_fd(), &mut termios) }, 0);
+        termios.c_lflag & libc::ECHO != 0
+    }
+
+    /// Type `input` into a password prompt on a pseudo-terminal, once the prompt was printed.
+    fn type_password(input: &'static [u8]) -> io::Result<SecureString> {
+        let (mut controller, tty) = open_pty();
+        assert!(echo_enabled(&tty));
+        let reader = thread::spawn(move || {
+            let result = read_password_from(&tty, "Password: ");
+            assert!(echo_enabled(&tty));
+            result
+        });
+
+        expect_output(&mut controller, "Password: ");
+        controller.write_all(input).unwrap();
+        let result = reader.join().unwrap();
+
+        // Nothing but the final newline is printed after the prompt
+        unsafe { libc::fcntl(controller.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) };
+        let mut output = Vec::new();
+        let _ = controller.read_to_end(&mut output);
+        assert_eq!(output, b"\r\n");
+        result
+    }
+
+    #[test]
+    fn test_read_password() {
+        assert_eq!(type_password(b"hunter2\n").unwrap().unsecure(), "hunter2");
+        assert_eq!(type_password("pässwörd🦄\r".as_bytes()).unwrap().unsecure(), "pässwörd🦄");
+        assert!(type_password(b"hunter2\n").unwrap().is_locked());
+    }
+
+    #[test]
+    fn test_line_editing() {
+        assert_eq!(type_password(b"hunter2\x7f3\n").unwrap().unsecure(), "hunter3");
+        assert_eq!(type_password("ab🦄\x08c\n".as_bytes()).unwrap().unsecure(), "abc");
+        assert_eq!(type_password(b"wrong\x15right\n").unwrap().unsecure(), "right");
+        assert_eq!(type_password(b"\x7f\n").unwrap().unsecure(), "");
+    }
+
+    #[test]
+    fn test_interrupt() {
+        assert_eq!(type_password(b"hun\x03").unwrap_err().kind(), io::ErrorKind::Interrupted);
+        assert_eq!(type_password(b"\x04").unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_confirmation() {
+        for (input, matches) in [(b"secret\nsecret\n", true), (b"secret\nsecreT\n", false)] {
+            let (mut controller, tty) = open_pty();
+            let reader = thread::spawn(move || read_password_confirmed_from(&tty, "Password: ", "Again: "));
+            expect_output(&mut controller, "Password: ");
+            controller.write_all(&input[..7]).unwrap();
+            expect_output(&mut controller, "Again: ");
+            controller.write_all(&input[7..]).unwrap();
+
+            match reader.join().unwrap() {
+                Ok(password) => assert!(matches && password.unsecure() == "secret"),
+                Err(error) => assert!(!matches && error.kind() == io::ErrorKind::InvalidData),
+            }
+        }
+    }
+
+    #[test]
+    fn test_restore_on_panic() {
+        let (_controller, tty) = open_pty();
+        let result = std::panic::catch_unwind(|| {
+            let _mode = RawMode::enable(&tty).unwrap();
+            assert!(!echo_enabled(&tty));
+            panic!("while reading");
+        });
+        assert!(result.is_err());
+        assert!(echo_enabled(&tty));
+    }
+}
@@ -1,37 +1,74 @@
 use core::fmt;
 use std::{
     borrow::{Borrow, BorrowMut},
+    hash::{Hash, Hasher},
     str::FromStr,
 };
 
 use zeroize::Zeroize;
 
-use crate::secure_utils::memlock;
+use crate::secure_utils::{
+    constant_time, keyed_hash,
+    lock_policy::{self, LockError},
+    memlock,
+};
 
 /// A data type suitable for storing sensitive information such as passwords and private keys in memory, that implements:
 ///
 /// - Automatic zeroing in `Drop`
 /// - Constant time comparison in `PartialEq` (does not short circuit on the first different character; but terminates instantly if strings have different length)
+/// - Hashing only a keyed digest in `Hash`, such that secrets can be used as keys in maps
 /// - Outputting `***SECRET***` to prevent leaking secrets into logs in `fmt::Debug` and `fmt::Display`
-/// - Automatic `mlock` to protect against leaking into swap (any unix)
-/// - Automatic `madvise(MADV_NOCORE/MADV_DONTDUMP)` to protect against leaking into core dumps (FreeBSD, DragonflyBSD, Linux)
+/// - Best-effort `mlock` to protect against leaking into swap (any unix)
+/// - Best-effort `madvise(MADV_NOCORE/MADV_DONTDUMP)` to protect against leaking into core dumps (FreeBSD, DragonflyBSD, Linux)
+///
+/// The array is stored inline, so only the memory it is created in is locked, and moving the array (e.g. returning it
+/// from a function) moves the contents out of that memory. Use [`SecureBox`](crate::SecureBox) to keep the contents
+/// locked at a fixed address.
 ///
 /// Comparisons using the `PartialEq` implementation are undefined behavior (and most likely wrong) if `T` has any padding bytes.
-#[derive(Eq, PartialEq, PartialOrd, Ord, Hash)]
+///
+/// `PartialOrd` and `Ord` are only implemented with the `insecure-ord` feature, since they are not constant time.
 pub struct SecureArray<T, const LENGTH: usize>
 where
     T: Copy + Zeroize,
 {
     pub(crate) content: [T; LENGTH],
+    // The array is stored inline, so moving it also moves `content` away from the memory that was locked.
+    // This remembers the locked memory, such that exactly that memory is unlocked again on drop, which balances the
+    // reference counts of the locked pages.
+    locked_at: Option<usize>,
 }
 
 impl<T, const LENGTH: usize> SecureArray<T, LENGTH>
 where
     T: Copy + Zeroize,
 {
-    pub fn new(mut content: [T; LENGTH]) -> Self {
-        memlock::mlock(content.as_mut_ptr(), content.len());
-        Self { content }
+    pub fn new(content: [T; LENGTH]) -> Self {
+        // Locking may panic depending on the lock policy, which drops the array and thereby zeroes its contents
+        let mut result = Self { content, locked_at: None };
+        if lock_policy::lock(result.content.as_mut_ptr(), LENGTH) {
+            result.locked_at = Some(result.content.as_ptr() as usize);
+        }
+        result
+    }
+
+    /// Like [`SecureArray::new`], but returns an error if the memory cannot be locked, regardless of the
+    /// [lock policy](crate::set_lock_policy). The contents are zeroed before the error is returned.
+    pub fn try_new(content: [T; LENGTH]) -> Result<Self, LockError> {
+        // Dropping the unlocked array on errors zeroes its contents
+        let mut result = Self { content, locked_at: None };
+        memlock::mlock(result.content.as_mut_ptr(), LENGTH)?;
+        result.locked_at = Some(result.content.as_ptr() as usize);
+        Ok(result)
+    }
+
+    /// Returns `true` if the array is still in the memory that was locked into RAM on creation, such that it cannot be
+    /// swapped to disk.
+    ///
+    /// Since the array is stored inline, this is `false` after the array was moved.
+    pub fn is_locked(&self) -> bool {
+        self.locked_at == Some(self.content.as_ptr() as usize)
     }
 
     /// Borrow the contents of the string.
@@ -56,6 +93,35 @@ impl<T: Copy + Zeroize, const LENGTH: usize> Clone for SecureArray<T, LENGTH> {
     }
 }
 
+impl<T: Copy + Zeroize, const LENGTH: usize> PartialEq for SecureArray<T, LENGTH> {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time::eq(self.unsecure(), other.unsecure())
+    }
+}
+
+impl<T: Copy + Zeroize, const LENGTH: usize> Eq for SecureArray<T, LENGTH> {}
+
+#[cfg(feature = "insecure-ord")]
+impl<T: Copy + Zeroize + PartialOrd, const LENGTH: usize> PartialOrd for SecureArray<T, LENGTH> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.unsecure().partial_cmp(other.unsecure())
+    }
+}
+
+#[cfg(feature = "insecure-ord")]
+impl<T: Copy + Zeroize + Ord, const LENGTH: usize> Ord for SecureArray<T, LENGTH> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.unsecure().cmp(other.unsecure())
+    }
+}
+
+// Only the keyed digest is passed to the hasher, such that it cannot observe the secret itself
+impl<T: Copy + Zeroize, const LENGTH: usize> Hash for SecureArray<T, LENGTH> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(keyed_hash::digest(constant_time::as_bytes(self.unsecure())))
+    }
+}
+
 // Creation
 impl<T, const LENGTH: usize> From<[T; LENGTH]> for SecureArray<T, LENGTH>
 where
@@ -126,7 +192,9 @@ where
 {
     fn drop(&mut self) {
         self.zero_out();
-        memlock::munlock(self.content.as_mut_ptr(), self.content.len());
+        if let Some(locked_at) = self.locked_at {
+            memlock::munlock(locked_at as *mut T, LENGTH);
+        }
     }
 }
 
@@ -162,6 +230,25 @@ mod tests {
         assert_eq!(my_sec.unsecure(), b"hello");
     }
 
+    #[test]
+    #[cfg(feature = "insecure-ord")]
+    fn test_ord() {
+        // Only the contents are compared, not where the arrays were locked
+        let locked = SecureArray::new(*b"hello");
+        let unlocked = SecureArray { content: *b"hello", locked_at: None };
+        assert!(locked == unlocked);
+        assert_eq!(locked.cmp(&unlocked), std::cmp::Ordering::Equal);
+        assert!(SecureArray::new(*b"hellO") < locked);
+        assert_eq!(std::collections::BTreeSet::from([locked, unlocked]).len(), 1);
+    }
+
+    #[test]
+    fn test_locked_after_move() {
+        // The heap memory of the box was never locked
+        let moved = Box::new(SecureArray::new([1u8; 32]));
+        assert!(!moved.is_locked());
+    }
+
     #[test]
     #[cfg_attr(feature = "pre", pre::pre)]
     fn test_zero_out() {
@@ -176,6 +263,12 @@ mod tests {
         assert_ne!(SecureArray::<_, 5>::from_str("hello").unwrap(), SecureArray::from_str("olleh").unwrap());
     }
 
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SecureArray<u8, 5>>();
+    }
+
     #[test]
     fn test_indexing() {
         let string: SecureArray<_, 5> = SecureArray::from_str("hello").unwrap();
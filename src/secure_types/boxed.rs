@@ -1,39 +1,106 @@
 use core::fmt;
 use std::{
+    alloc::Layout,
     borrow::{Borrow, BorrowMut},
+    hash::{Hash, Hasher},
     mem::MaybeUninit,
+    ptr::NonNull,
 };
 
+use allocator_api2::{
+    alloc::{Allocator, Global},
+    boxed::Box,
+};
 use zeroize::Zeroize;
 
-use crate::secure_utils::memlock;
+use crate::secure_utils::{
+    constant_time, keyed_hash,
+    lock_policy::{self, LockError},
+    memlock,
+};
 
 /// A data type suitable for storing sensitive information such as passwords and private keys in memory, that implements:
 ///
 /// - Automatic zeroing in `Drop`
 /// - Constant time comparison in `PartialEq` (does not short circuit on the first different character; but terminates instantly if strings have different length)
+/// - Hashing only a keyed digest in `Hash`, such that secrets can be used as keys in maps
 /// - Outputting `***SECRET***` to prevent leaking secrets into logs in `fmt::Debug` and `fmt::Display`
 /// - Automatic `mlock` to protect against leaking into swap (any unix)
 /// - Automatic `madvise(MADV_NOCORE/MADV_DONTDUMP)` to protect against leaking into core dumps (FreeBSD, DragonflyBSD, Linux)
+/// - Automatic `madvise(MADV_WIPEONFORK)`/`minherit(INHERIT_ZERO)` to protect against leaking into child processes
+///   created with `fork`, for the pages that are occupied only by the box (any unix, see [`SecureBox::keep_on_fork`])
+/// - Optionally storing the contents on dedicated pages with guard pages, using [`SecureAllocator`](crate::SecureAllocator)
 ///
 /// Comparisons using the `PartialEq` implementation are undefined behavior (and most likely wrong) if `T` has any padding bytes.
-#[derive(Eq, PartialEq, PartialOrd, Ord, Hash)]
-pub struct SecureBox<T>
+///
+/// `PartialOrd` and `Ord` are only implemented with the `insecure-ord` feature, since they are not constant time.
+pub struct SecureBox<T, A = Global>
 where
     T: Copy,
+    A: Allocator,
 {
     // This is an `Option` to avoid UB in the destructor, outside the destructor, it is always
     // `Some(_)`
-    content: Option<Box<T>>,
+    content: Option<Box<T, A>>,
+    locked: bool,
+    wipe_on_fork: bool,
 }
 
 impl<T> SecureBox<T>
 where
     T: Copy,
 {
-    pub fn new(mut cont: Box<T>) -> Self {
-        memlock::mlock(&mut cont, 1);
-        SecureBox { content: Some(cont) }
+    pub fn new(cont: std::boxed::Box<T>) -> Self {
+        Self::new_in(from_std_box(cont))
+    }
+
+    /// Like [`SecureBox::new`], but returns an error if the memory cannot be locked, regardless of the
+    /// [lock policy](crate::set_lock_policy). The contents are zeroed before the error is returned.
+    pub fn try_new(cont: std::boxed::Box<T>) -> Result<Self, LockError> {
+        Self::try_new_in(from_std_box(cont))
+    }
+}
+
+impl<T, A> SecureBox<T, A>
+where
+    T: Copy,
+    A: Allocator,
+{
+    /// Create a `SecureBox` from a box with a custom allocator, e.g. [`SecureAllocator`](crate::SecureAllocator).
+    pub fn new_in(cont: Box<T, A>) -> Self {
+        // Locking may panic depending on the lock policy, which drops the box and thereby zeroes its contents
+        let mut result = SecureBox { content: Some(cont), locked: false, wipe_on_fork: false };
+        result.locked = lock_policy::lock(result.unsecure_mut() as *mut T, 1);
+        memlock::set_wipe_on_fork(result.unsecure_mut() as *mut T, 1, true);
+        result.wipe_on_fork = true;
+        result
+    }
+
+    /// Like [`SecureBox::new_in`], but returns an error if the memory cannot be locked, regardless of the
+    /// [lock policy](crate::set_lock_policy). The contents are zeroed before the error is returned.
+    pub fn try_new_in(cont: Box<T, A>) -> Result<Self, LockError> {
+        // Dropping the unlocked box on errors zeroes its contents
+        let mut result = SecureBox { content: Some(cont), locked: false, wipe_on_fork: false };
+        memlock::mlock(result.unsecure_mut() as *mut T, 1)?;
+        result.locked = true;
+        memlock::set_wipe_on_fork(result.unsecure_mut() as *mut T, 1, true);
+        result.wipe_on_fork = true;
+        Ok(result)
+    }
+
+    /// Returns `true` if the memory of the box is locked into RAM, such that it cannot be swapped to disk.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Let child processes created with `fork` inherit the contents of the box.
+    ///
+    /// By default, the pages that are occupied only by the box appear zeroed in child processes. Pages that are
+    /// shared with other memory are always inherited, so small boxes should use
+    /// [`SecureAllocator`](crate::SecureAllocator) to be wiped reliably.
+    pub fn keep_on_fork(&mut self) {
+        memlock::set_wipe_on_fork(self.unsecure_mut() as *mut T, 1, false);
+        self.wipe_on_fork = false;
     }
 
     /// Borrow the contents of the string.
@@ -47,36 +114,99 @@ where
     }
 }
 
-impl<T: Copy> Clone for SecureBox<T> {
+#[cfg(feature = "serde")]
+impl<T, A> SecureBox<MaybeUninit<T>, A>
+where
+    T: Copy,
+    A: Allocator,
+{
+    /// Convert into a `SecureBox<T, A>` without moving the contents out of the locked memory.
+    ///
+    /// # Safety
+    ///
+    /// The contents must be initialized, see [`MaybeUninit::assume_init`].
+    pub(crate) unsafe fn assume_init(mut self) -> SecureBox<T, A> {
+        let content = self.content.take().unwrap().assume_init();
+        let result = SecureBox {
+            content: Some(content),
+            locked: self.locked,
+            wipe_on_fork: self.wipe_on_fork,
+        };
+        // The contents were moved out, there is nothing left to zero, unlock or deallocate
+        std::mem::forget(self);
+        result
+    }
+}
+
+/// Convert a box of the standard library into one of `allocator_api2`, without copying.
+fn from_std_box<T>(cont: std::boxed::Box<T>) -> Box<T> {
+    // Safety: `Global` is the global allocator, which allocated the standard library box
+    unsafe { Box::from_raw(std::boxed::Box::into_raw(cont)) }
+}
+
+impl<T: Copy, A: Allocator + Clone> Clone for SecureBox<T, A> {
     fn clone(&self) -> Self {
-        Self::new(self.content.clone().unwrap())
+        let content = self.content.as_ref().unwrap();
+        Self::new_in(Box::new_in(**content, Box::allocator(content).clone()))
+    }
+}
+
+impl<T: Copy, A: Allocator> PartialEq for SecureBox<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time::eq(std::slice::from_ref(self.unsecure()), std::slice::from_ref(other.unsecure()))
+    }
+}
+
+impl<T: Copy, A: Allocator> Eq for SecureBox<T, A> {}
+
+#[cfg(feature = "insecure-ord")]
+impl<T: Copy + PartialOrd, A: Allocator> PartialOrd for SecureBox<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.unsecure().partial_cmp(other.unsecure())
+    }
+}
+
+#[cfg(feature = "insecure-ord")]
+impl<T: Copy + Ord, A: Allocator> Ord for SecureBox<T, A> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.unsecure().cmp(other.unsecure())
+    }
+}
+
+// Only the keyed digest is passed to the hasher, such that it cannot observe the secret itself
+impl<T: Copy, A: Allocator> Hash for SecureBox<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(keyed_hash::digest(constant_time::as_bytes(std::slice::from_ref(self.unsecure()))))
     }
 }
 
 // Delegate indexing
-impl<T, U> std::ops::Index<U> for SecureBox<T>
+impl<T, U, A> std::ops::Index<U> for SecureBox<T, A>
 where
     T: std::ops::Index<U> + Copy,
+    A: Allocator,
 {
     type Output = <T as std::ops::Index<U>>::Output;
 
     fn index(&self, index: U) -> &Self::Output {
-        std::ops::Index::index(self.content.as_ref().unwrap().as_ref(), index)
+        std::ops::Index::index(self.unsecure(), index)
     }
 }
 
 // Borrowing
-impl<T> Borrow<T> for SecureBox<T>
+impl<T, A> Borrow<T> for SecureBox<T, A>
 where
     T: Copy,
+    A: Allocator,
 {
     fn borrow(&self) -> &T {
         self.content.as_ref().unwrap()
     }
 }
-impl<T> BorrowMut<T> for SecureBox<T>
+impl<T, A> BorrowMut<T> for SecureBox<T, A>
 where
     T: Copy,
+    A: Allocator,
 {
     fn borrow_mut(&mut self) -> &mut T {
         self.content.as_mut().unwrap()
@@ -84,9 +214,10 @@ where
 }
 
 // Overwrite memory with zeros when we're done
-impl<T> Drop for SecureBox<T>
+impl<T, A> Drop for SecureBox<T, A>
 where
     T: Copy,
+    A: Allocator,
 {
     #[cfg_attr(feature = "pre", pre::pre)]
     fn drop(&mut self) {
@@ -94,7 +225,7 @@ where
         // see an invalid type, if `T` does not support an all-zero byte-pattern
         // Instead we manually destruct the box and only handle the potentially invalid values
         // behind the pointer
-        let ptr = Box::into_raw(self.content.take().unwrap());
+        let (ptr, alloc) = Box::into_raw_with_allocator(self.content.take().unwrap());
 
         // There is no need to worry about dropping the contents, because `T: Copy` and `Copy`
         // types cannot implement `Drop`
@@ -103,32 +234,39 @@ where
             std::slice::from_raw_parts_mut::<MaybeUninit<u8>>(ptr as *mut MaybeUninit<u8>, std::mem::size_of::<T>()).zeroize();
         }
 
-        memlock::munlock(ptr, 1);
+        if self.locked {
+            memlock::munlock(ptr, 1);
+        }
+        if self.wipe_on_fork {
+            memlock::set_wipe_on_fork(ptr, 1, false);
+        }
 
         // Deallocate only non-zero-sized types, because otherwise it's UB
         if std::mem::size_of::<T>() != 0 {
             // Safety:
             // This way to manually deallocate is advertised in the documentation of `Box::into_raw`.
-            // The box was allocated with the global allocator and a layout of `T` and is thus
+            // The box was allocated with `alloc` and a layout of `T` and is thus
             // deallocated using the same allocator and layout here.
-            unsafe { std::alloc::dealloc(ptr as *mut u8, std::alloc::Layout::new::<T>()) };
+            unsafe { alloc.deallocate(NonNull::new_unchecked(ptr as *mut u8), Layout::new::<T>()) };
         }
     }
 }
 
 // Make sure sensitive information is not logged accidentally
-impl<T> fmt::Debug for SecureBox<T>
+impl<T, A> fmt::Debug for SecureBox<T, A>
 where
     T: Copy,
+    A: Allocator,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str("***SECRET***").map_err(|_| fmt::Error)
     }
 }
 
-impl<T> fmt::Display for SecureBox<T>
+impl<T, A> fmt::Display for SecureBox<T, A>
 where
     T: Copy,
+    A: Allocator,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str("***SECRET***").map_err(|_| fmt::Error)
@@ -194,4 +332,52 @@ mod tests {
         };
         assert_eq!(final_key.unsecure(), &[0; 32]);
     }
+
+    #[test]
+    fn test_zero_sized() {
+        let unit = SecureBox::new(Box::new(()));
+        assert!(unit.is_locked());
+        assert!(unit == unit.clone());
+    }
+
+    /// Returns the total `Locked` size of the mappings overlapping `range` as reported by `/proc/self/smaps`, in kB.
+    #[cfg(target_os = "linux")]
+    fn locked_kilobytes(range: std::ops::Range<usize>) -> usize {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+        let mut in_range = false;
+        let mut locked = 0;
+        for line in smaps.lines() {
+            let mut fields = line.split_whitespace();
+            let first = fields.next().unwrap_or_default();
+            if let Some((start, end)) = first.split_once('-') {
+                if let (Ok(start), Ok(end)) = (usize::from_str_radix(start, 16), usize::from_str_radix(end, 16)) {
+                    // Mappings may be split, e.g. by `madvise`
+                    in_range = start < range.end && range.start < end;
+                    continue;
+                }
+            }
+            if in_range && first == "Locked:" {
+                locked += fields.next().unwrap().parse::<usize>().unwrap();
+            }
+        }
+        locked
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_locks_heap() {
+        // Spans whole pages of the heap, but stays well below the common `RLIMIT_MEMLOCK` of 64 KiB
+        const LENGTH: usize = 1 << 12;
+        let key = SecureBox::new(Box::new([0u64; LENGTH]));
+        if !key.is_locked() {
+            // Other tests lock memory concurrently, which may exhaust a low limit of unprivileged users
+            let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+            assert_eq!(unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) }, 0);
+            assert!(limit.rlim_cur < 1 << 20, "locking failed despite a memlock limit of {} bytes", limit.rlim_cur);
+            return;
+        }
+        let address = key.unsecure().as_ptr() as usize;
+        let size = std::mem::size_of::<[u64; LENGTH]>();
+        assert!(locked_kilobytes(address..address + size) * 1024 >= size);
+    }
 }
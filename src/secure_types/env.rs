@@ -0,0 +1,209 @@
+use std::io;
+
+use allocator_api2::alloc::Global;
+use zeroize::Zeroize;
+
+use crate::SecureString;
+
+impl SecureString {
+    /// Copy the value of the environment variable `name` into a new `SecureString`.
+    ///
+    /// The value is read through [`std::env::var_os`], which synchronizes with other threads accessing the environment,
+    /// and the temporary copy is zeroed. Returns an error of kind [`NotFound`](io::ErrorKind::NotFound) if the variable
+    /// is not set, or [`InvalidData`](io::ErrorKind::InvalidData) if its value is not valid UTF-8.
+    pub fn from_env(name: &str) -> io::Result<Self> {
+        read(name, false)
+    }
+
+    /// Like [`SecureString::from_env`], but also removes the variable, such that child processes and later reads of the
+    /// environment cannot see it anymore.
+    ///
+    /// On Linux, the value is also overwritten with zeros if it is still stored in the initial environment block that
+    /// the kernel set up for the process, and the process has a single thread. libc does not own that storage, so
+    /// nothing references it after the variable is removed. Values set with [`std::env::set_var`] are owned by libc,
+    /// which may still reference them, so they are not overwritten.
+    ///
+    /// Like [`std::env::remove_var`], this should be called before other threads are spawned that may access the
+    /// environment.
+    pub fn take_env(name: &str) -> io::Result<Self> {
+        read(name, true)
+    }
+}
+
+fn read(name: &str, take: bool) -> io::Result<SecureString> {
+    // `std::env` also treats these names as unset, and `remove_var` may panic for them
+    if name.is_empty() || name.contains(['=', '\0']) {
+        return Err(not_found());
+    }
+    let mut value = std::env::var_os(name).ok_or_else(not_found)?.into_encoded_bytes();
+    let secret = std::str::from_utf8(&value).map(|value| SecureString::from_str_in(value, Global));
+    value.zeroize();
+
+    // The variable is left alone on errors
+    let secret = secret.map_err(|_| not_unicode())?;
+    if take {
+        #[cfg(target_os = "linux")]
+        let initial = initial_env::find(name);
+        std::env::remove_var(name);
+        #[cfg(target_os = "linux")]
+        if let Some(value) = initial {
+            initial_env::wipe(value);
+        }
+    }
+    Ok(secret)
+}
+
+/// Overwriting values in the initial environment block, which the kernel places above the stack of the process.
+///
+/// This is only done while the process has a single thread, such that no other thread can access the environment
+/// concurrently.
+#[cfg(target_os = "linux")]
+mod initial_env {
+    use std::{
+        ffi::{CStr, CString},
+        ops::Range,
+    };
+
+    use zeroize::Zeroize;
+
+    extern "C" {
+        static environ: *const *const libc::c_char;
+    }
+
+    /// The memory of the value of the variable `name`, if it lies in the initial environment block and the process
+    /// has a single thread.
+    pub fn find(name: &str) -> Option<Range<usize>> {
+        if !single_threaded() {
+            return None;
+        }
+        let block = block()?;
+        let name = CString::new(name).ok()?;
+        let ptr = unsafe { libc::getenv(name.as_ptr()) };
+        if ptr.is_null() {
+            return None;
+        }
+        let start = ptr as usize;
+        let value = start..start + unsafe { CStr::from_ptr(ptr) }.to_bytes().len();
+        (block.start <= value.start && value.end <= block.end).then_some(value)
+    }
+
+    /// Overwrite `value` with zeros, unless a variable still references it.
+    pub fn wipe(value: Range<usize>) {
+        unsafe {
+            let mut entry = environ;
+            while !entry.is_null() && !(*entry).is_null() {
+                let start = *entry as usize;
+                let end = start + CStr::from_ptr(*entry).to_bytes().len();
+                if start <= value.end && value.start <= end {
+                    return;
+                }
+                entry = entry.add(1);
+            }
+            std::slice::from_raw_parts_mut(value.start as *mut u8, value.len()).zeroize();
+        }
+    }
+
+    /// The initial environment block, as reported by the `env_start` and `env_end` fields of `/proc/self/stat`.
+    fn block() -> Option<Range<usize>> {
+        let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+        // The fields after the command name, which may contain spaces, start with the third field
+        let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace().skip(47);
+        let start = fields.next()?.parse().ok()?;
+        let end = fields.next()?.parse().ok()?;
+        Some(start..end)
+    }
+
+    fn single_threaded() -> bool {
+        std::fs::read_to_string("/proc/self/status")
+            .is_ok_and(|status| status.lines().any(|line| line.split_whitespace().eq(["Threads:", "1"])))
+    }
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "the environment variable is not set")
+}
+
+fn not_unicode() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "the environment variable is not valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::SecureString;
+
+    #[test]
+    fn test_from_env() {
+        std::env::set_var("SECURE_STRING_TEST_FROM_ENV", "hunter2");
+        let password = SecureString::from_env("SECURE_STRING_TEST_FROM_ENV").unwrap();
+        assert_eq!(password.unsecure(), "hunter2");
+        assert!(password.is_locked());
+        assert_eq!(std::env::var("SECURE_STRING_TEST_FROM_ENV").unwrap(), "hunter2");
+
+        assert_eq!(SecureString::from_env("SECURE_STRING_TEST_UNSET").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(SecureString::from_env("").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(SecureString::from_env("A=B").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_from_env_not_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        std::env::set_var("SECURE_STRING_TEST_NOT_UNICODE", std::ffi::OsStr::from_bytes(b"\xff"));
+        let error = SecureString::take_env("SECURE_STRING_TEST_NOT_UNICODE").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // The variable is left alone on errors
+        assert!(std::env::var_os("SECURE_STRING_TEST_NOT_UNICODE").is_some());
+    }
+
+    #[test]
+    fn test_take_env() {
+        std::env::set_var("SECURE_STRING_TEST_TAKE_ENV", "correct horse battery staple");
+        let password = SecureString::take_env("SECURE_STRING_TEST_TAKE_ENV").unwrap();
+        assert_eq!(password.unsecure(), "correct horse battery staple");
+        assert!(password.is_locked());
+        assert!(std::env::var_os("SECURE_STRING_TEST_TAKE_ENV").is_none());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_take_initial_env() {
+        const NAME: &str = "SECURE_STRING_TEST_INITIAL_ENV";
+        if std::env::var_os(NAME).is_none() {
+            // Run this test again in a new process, with the variable in its initial environment block
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "secure_types::env::tests::test_take_initial_env", "--quiet"])
+                .env(NAME, "hunter2")
+                .stdout(std::process::Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+
+        let storage = unsafe { libc::getenv(c"SECURE_STRING_TEST_INITIAL_ENV".as_ptr()) };
+        let value = || unsafe { std::slice::from_raw_parts(storage as *const u8, 7) };
+        // The test harness runs tests in their own thread, but a forked child has a single thread
+        unsafe {
+            match libc::fork() {
+                0 => {
+                    let password = SecureString::take_env(NAME);
+                    let wiped = password.is_ok_and(|password| password.unsecure() == "hunter2") && value() == [0; 7];
+                    libc::_exit(wiped as libc::c_int)
+                },
+                -1 => panic!("fork failed"),
+                child => {
+                    let mut status = 0;
+                    libc::waitpid(child, &mut status, 0);
+                    assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 1);
+                },
+            }
+        }
+
+        // Other threads may access the environment, so the value is only removed
+        assert_eq!(SecureString::take_env(NAME).unwrap().unsecure(), "hunter2");
+        assert_eq!(value(), b"hunter2");
+    }
+}
@@ -0,0 +1,390 @@
+use core::fmt;
+use std::{
+    fs::File,
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use allocator_api2::alloc::Allocator;
+
+use crate::{SecureBytes, SecureString, SecureVec};
+
+/// Options for reading secrets from files, see [`SecureBytes::read_from_file_with`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadOptions {
+    strip_trailing_newline: bool,
+    private_only: bool,
+}
+
+impl ReadOptions {
+    /// Read the file as it is, regardless of its permissions.
+    pub const fn new() -> Self {
+        Self { strip_trailing_newline: false, private_only: false }
+    }
+
+    /// Remove a single trailing `\n` or `\r\n`, which most editors and `echo` append to the secret.
+    pub const fn strip_trailing_newline(mut self, strip: bool) -> Self {
+        self.strip_trailing_newline = strip;
+        self
+    }
+
+    /// Refuse to read files that are readable or writable by the group or others, with an error of kind
+    /// [`PermissionDenied`](io::ErrorKind::PermissionDenied). This is only checked on unix.
+    pub const fn private_only(mut self, private: bool) -> Self {
+        self.private_only = private;
+        self
+    }
+}
+
+impl SecureBytes {
+    /// Read all bytes from `reader` into a new `SecureBytes`.
+    ///
+    /// The bytes are read directly into locked memory, which grows securely like [`SecureVec::reserve`].
+    pub fn from_reader(reader: impl Read) -> io::Result<Self> {
+        Self::from_reader_with_capacity(reader, 64)
+    }
+
+    /// Read the contents of the file at `path` into a new `SecureBytes`.
+    ///
+    /// The locked memory is sized from the file metadata, such that it does not need to grow while reading.
+    pub fn read_from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from_file_with(path, ReadOptions::new())
+    }
+
+    /// Like [`SecureBytes::read_from_file`], but with the given options, e.g. to refuse files that others can read.
+    pub fn read_from_file_with(path: impl AsRef<Path>, options: ReadOptions) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        #[cfg(unix)]
+        if options.private_only {
+            use std::os::unix::fs::PermissionsExt;
+
+            if metadata.permissions().mode() & 0o077 != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "the secret file is accessible by the group or others",
+                ));
+            }
+        }
+
+        // One more byte than the file size, such that reaching the end does not need to grow the memory
+        let mut secret = Self::from_reader_with_capacity(file, metadata.len() as usize + 1)?;
+        if options.strip_trailing_newline {
+            secret.strip_trailing_newline();
+        }
+        Ok(secret)
+    }
+
+    fn from_reader_with_capacity(mut reader: impl Read, capacity: usize) -> io::Result<Self> {
+        let mut secret = Self::new_in(allocator_api2::vec::Vec::with_capacity(capacity));
+        secret.read_to_end(&mut reader)?;
+        Ok(secret)
+    }
+}
+
+impl<A: Allocator + Clone> SecureVec<u8, A> {
+    /// Append all bytes from `reader`, growing securely like [`SecureVec::reserve`], and return their number.
+    ///
+    /// Use this instead of [`io::copy`], which copies the bytes through a buffer on the stack that is never zeroed.
+    /// The bytes are copied straight from the buffer of `reader`, e.g. from a [`SecureCursor`] into the vector.
+    pub fn copy_from(&mut self, reader: &mut impl BufRead) -> io::Result<u64> {
+        let mut copied = 0;
+        loop {
+            let len = match reader.fill_buf() {
+                Ok([]) => return Ok(copied),
+                Ok(buf) => {
+                    self.extend_from_slice(buf);
+                    buf.len()
+                },
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            reader.consume(len);
+            copied += len as u64;
+        }
+    }
+
+    /// Read all bytes from `reader` and append them, without ever copying them to unlocked memory.
+    fn read_to_end(&mut self, reader: &mut impl Read) -> io::Result<()> {
+        loop {
+            if self.len() == self.capacity() {
+                self.reserve(64);
+            }
+            // Read into the zeroed spare capacity
+            let start = self.len();
+            self.resize(self.capacity(), 0);
+            let result = reader.read(&mut self.content[start..]);
+            let read = *result.as_ref().unwrap_or(&0);
+            self.truncate(start + read);
+            match result {
+                Ok(0) => return Ok(()),
+                Ok(_) => {},
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {},
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn strip_trailing_newline(&mut self) {
+        if self.unsecure().ends_with(b"\n") {
+            self.pop();
+            if self.unsecure().ends_with(b"\r") {
+                self.pop();
+            }
+        }
+    }
+}
+
+/// Appends the written bytes, growing securely like [`SecureVec::reserve`].
+impl<A: Allocator + Clone> Write for SecureVec<u8, A> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<A: Allocator> SecureVec<u8, A> {
+    /// Create a [`SecureCursor`] to read the contents of the vector with [`Read`] or [`BufRead`].
+    pub fn cursor(&self) -> SecureCursor<'_> {
+        SecureCursor::new(self.unsecure())
+    }
+}
+
+/// Reads a borrowed secret with [`Read`], [`BufRead`] and [`Seek`], like `std::io::Cursor`.
+///
+/// Use this to stream secrets into encoders, hashers or TLS libraries. Reading copies the secret into the buffers
+/// provided by the caller, which should be secure types as well. To copy into a [`SecureVec`], use
+/// [`SecureVec::copy_from`] or [`SecureBytes::from_reader`] rather than [`io::copy`], whose intermediate buffer is
+/// never zeroed.
+///
+/// Outputs `***SECRET***` in `fmt::Debug`, like the secure types.
+pub struct SecureCursor<'a> {
+    secret: &'a [u8],
+    position: u64,
+}
+
+impl<'a> SecureCursor<'a> {
+    /// Create a cursor at the start of `secret`.
+    pub fn new(secret: &'a [u8]) -> Self {
+        Self { secret, position: 0 }
+    }
+
+    /// Returns the current position of the cursor.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Set the position of the cursor, which may be past the end of the secret.
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    /// The part of the secret after the current position.
+    fn remaining(&self) -> &'a [u8] {
+        let start = self.position.min(self.secret.len() as u64) as usize;
+        &self.secret[start..]
+    }
+}
+
+impl<'a> Read for SecureCursor<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.remaining();
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<'a> BufRead for SecureCursor<'a> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.remaining())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
+    }
+}
+
+impl<'a> Seek for SecureCursor<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(position) => {
+                self.position = position;
+                return Ok(position);
+            },
+            SeekFrom::End(offset) => (self.secret.len() as u64, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        match base.checked_add_signed(offset) {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            },
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for SecureCursor<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("***SECRET***").map_err(|_| fmt::Error)
+    }
+}
+
+impl SecureString {
+    /// Create a [`SecureCursor`] to read the contents of the string with [`Read`] or [`BufRead`].
+    pub fn cursor(&self) -> SecureCursor<'_> {
+        self.0.cursor()
+    }
+
+    /// Read all bytes from `reader` into a new `SecureString`.
+    ///
+    /// Returns an error of kind [`InvalidData`](io::ErrorKind::InvalidData) if the bytes are not valid UTF-8,
+    /// in which case they are zeroed.
+    pub fn from_reader(reader: impl Read) -> io::Result<Self> {
+        Self::from_utf8(SecureBytes::from_reader(reader)?)
+    }
+
+    /// Read the contents of the file at `path` into a new `SecureString`, removing a single trailing newline.
+    ///
+    /// See [`SecureBytes::read_from_file`] for details.
+    pub fn read_from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from_file_with(path, ReadOptions::new().strip_trailing_newline(true))
+    }
+
+    /// Like [`SecureString::read_from_file`], but with the given options.
+    pub fn read_from_file_with(path: impl AsRef<Path>, options: ReadOptions) -> io::Result<Self> {
+        Self::from_utf8(SecureBytes::read_from_file_with(path, options)?)
+    }
+
+    fn from_utf8(bytes: SecureBytes) -> io::Result<Self> {
+        match std::str::from_utf8(bytes.unsecure()) {
+            Ok(_) => Ok(SecureString(bytes)),
+            Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "the secret is not valid UTF-8")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, BufRead, Read, Seek, SeekFrom, Write},
+        path::PathBuf,
+    };
+
+    use super::{ReadOptions, SecureCursor};
+    use crate::{SecureAllocator, SecureBytes, SecureString, SecureVec};
+
+    /// Write `contents` to a new file with the given unix `mode`, which is removed when the returned guard is dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8], mode: u32) -> Self {
+            let path = std::env::temp_dir().join(format!("secure-string-{}-{name}", std::process::id()));
+            std::fs::write(&path, contents).unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            }
+            #[cfg(not(unix))]
+            let _ = mode;
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let long = "secret".repeat(1000);
+        assert_eq!(SecureBytes::from_reader(long.as_bytes()).unwrap().unsecure(), long.as_bytes());
+        assert!(SecureBytes::from_reader(&b""[..]).unwrap().is_locked());
+        // No newline is stripped from readers
+        assert_eq!(SecureString::from_reader(&b"hunter2\n"[..]).unwrap().unsecure(), "hunter2\n");
+        assert_eq!(SecureString::from_reader(&b"\xff"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_read_from_file() {
+        let file = TempFile::new("api-key", b"hunter2\r\n", 0o600);
+        let key = SecureString::read_from_file(&file.0).unwrap();
+        assert_eq!(key.unsecure(), "hunter2");
+        assert!(key.is_locked());
+        assert_eq!(SecureBytes::read_from_file(&file.0).unwrap().unsecure(), b"hunter2\r\n");
+        // The memory is sized from the metadata
+        assert_eq!(SecureBytes::read_from_file(&file.0).unwrap().capacity(), 10);
+
+        let options = ReadOptions::new().private_only(true).strip_trailing_newline(true);
+        assert_eq!(SecureBytes::read_from_file_with(&file.0, options).unwrap().unsecure(), b"hunter2");
+        let empty = TempFile::new("empty", b"\n", 0o600);
+        assert_eq!(SecureString::read_from_file(&empty.0).unwrap().unsecure(), "");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_private_only() {
+        let file = TempFile::new("shared-key", b"hunter2", 0o640);
+        assert_eq!(SecureString::read_from_file(&file.0).unwrap().unsecure(), "hunter2");
+        let error = SecureString::read_from_file_with(&file.0, ReadOptions::new().private_only(true)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_write() {
+        let mut secret = SecureVec::from_slice_in(b"key: ", SecureAllocator::new());
+        write!(secret, "{}", 0x5ec2e7).unwrap();
+        secret.write_all(&[b'!'; 1000]).unwrap();
+        assert_eq!(&secret.unsecure()[..12], b"key: 6210279");
+        assert_eq!(secret.len(), 1012);
+        assert!(secret.is_locked());
+    }
+
+    #[test]
+    fn test_cursor() {
+        let secret = SecureString::from("correct horse\nbattery staple");
+        let mut cursor = secret.cursor();
+        let mut word = [0; 7];
+        cursor.read_exact(&mut word).unwrap();
+        assert_eq!(&word, b"correct");
+        assert_eq!(cursor.position(), 7);
+
+        let mut lines = secret.cursor().lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "correct horse");
+        assert_eq!(lines.next().unwrap().unwrap(), "battery staple");
+        assert!(lines.next().is_none());
+
+        assert_eq!(cursor.seek(SeekFrom::End(-6)).unwrap(), 22);
+        assert_eq!(cursor.fill_buf().unwrap(), b"staple");
+        cursor.consume(2);
+        assert_eq!(cursor.seek(SeekFrom::Current(-1)).unwrap(), 23);
+        assert_eq!(cursor.seek(SeekFrom::Current(-100)).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        cursor.set_position(100);
+        assert_eq!(cursor.read(&mut word).unwrap(), 0);
+        assert_eq!(format!("{cursor:?}"), "***SECRET***");
+
+        // Streaming from one secret into another
+        let mut copy = SecureBytes::from_reader(SecureCursor::new(secret.unsecure().as_bytes())).unwrap();
+        assert_eq!(copy.unsecure(), secret.unsecure().as_bytes());
+        assert_eq!(copy.copy_from(&mut secret.cursor()).unwrap(), 28);
+        assert_eq!(copy.len(), 2 * secret.unsecure().len());
+        assert_eq!(&copy.unsecure()[28..], secret.unsecure().as_bytes());
+    }
+}
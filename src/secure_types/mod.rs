@@ -1,4 +1,7 @@
 pub mod array;
 pub mod boxed;
+pub mod env;
+pub mod io;
+pub mod protected;
 pub mod string;
 pub mod vec;
@@ -0,0 +1,357 @@
+use core::fmt;
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Mutex, PoisonError},
+};
+
+use zeroize::Zeroize;
+
+use crate::{
+    secure_utils::allocator::{protect, Protection},
+    SecureAllocator, SecureBox, SecureVec,
+};
+
+/// Grants access to the pages of a [`SecureAllocator`] allocation only while a guard exists.
+struct PageProtection {
+    ptr: usize,
+    byte_num: usize,
+    // The number of live read guards
+    readers: Mutex<usize>,
+}
+
+impl PageProtection {
+    /// Protect the pages touched by `byte_num` bytes starting at `ptr`, which must be allocated by [`SecureAllocator`].
+    fn new(ptr: *const u8, byte_num: usize) -> Self {
+        let protection = Self { ptr: ptr as usize, byte_num, readers: Mutex::new(0) };
+        protection.set(Protection::NoAccess);
+        protection
+    }
+
+    fn set(&self, protection: Protection) {
+        // Empty allocations do not own any pages
+        if self.byte_num != 0 {
+            unsafe { protect(self.ptr as *const u8, self.byte_num, protection) }
+        }
+    }
+
+    fn acquire_read(&self) {
+        let mut readers = self.readers.lock().unwrap_or_else(PoisonError::into_inner);
+        if *readers == 0 {
+            self.set(Protection::ReadOnly);
+        }
+        *readers += 1;
+    }
+
+    fn release_read(&self) {
+        let mut readers = self.readers.lock().unwrap_or_else(PoisonError::into_inner);
+        *readers -= 1;
+        if *readers == 0 {
+            self.set(Protection::NoAccess);
+        }
+    }
+}
+
+/// Read access to a protected secret, see [`ProtectedVec::read`] and [`ProtectedBox::read`].
+///
+/// The secret becomes inaccessible again when the last read guard is dropped.
+pub struct ReadGuard<'a, T: ?Sized> {
+    protection: &'a PageProtection,
+    value: &'a T,
+}
+
+impl<'a, T: ?Sized> Deref for ReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> Drop for ReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.protection.release_read();
+    }
+}
+
+/// Write access to a protected secret, see [`ProtectedVec::write`] and [`ProtectedBox::write`].
+///
+/// The secret becomes inaccessible again when the guard is dropped.
+pub struct WriteGuard<'a, T: ?Sized> {
+    protection: &'a PageProtection,
+    value: &'a mut T,
+}
+
+impl<'a, T: ?Sized> Deref for WriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for WriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> Drop for WriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.protection.set(Protection::NoAccess);
+    }
+}
+
+/// A [`SecureVec`] whose memory cannot be accessed at all, unless it is borrowed through a guard.
+///
+/// The contents are stored on dedicated pages by [`SecureAllocator`], which are mapped with `PROT_NONE` while no guard
+/// exists, like with libsodium's `sodium_mprotect_noaccess`. Stray pointers and memory disclosure bugs then fault
+/// instead of reading the secret.
+///
+/// Changing the access permissions requires a system call, so the guards should be kept for short periods.
+/// Access permissions can only be changed on unix, on other platforms this behaves like a `SecureVec`.
+pub struct ProtectedVec<T>
+where
+    T: Copy + Zeroize,
+{
+    content: SecureVec<T, SecureAllocator>,
+    protection: PageProtection,
+}
+
+impl<T> ProtectedVec<T>
+where
+    T: Copy + Zeroize,
+{
+    pub fn new(content: SecureVec<T, SecureAllocator>) -> Self {
+        let protection =
+            PageProtection::new(content.content.as_ptr() as *const u8, content.content.capacity() * std::mem::size_of::<T>());
+        Self { content, protection }
+    }
+
+    /// Copy `slice` into a new `ProtectedVec`.
+    pub fn from_slice(slice: &[T]) -> Self {
+        Self::new(SecureVec::from_slice_in(slice, SecureAllocator::new()))
+    }
+
+    /// Returns `true` if the memory of the vector is locked into RAM, such that it cannot be swapped to disk.
+    pub fn is_locked(&self) -> bool {
+        self.content.is_locked()
+    }
+
+    /// Make the contents readable until the returned guard and all other read guards are dropped.
+    pub fn read(&self) -> ReadGuard<'_, [T]> {
+        self.protection.acquire_read();
+        ReadGuard { protection: &self.protection, value: self.content.unsecure() }
+    }
+
+    /// Make the contents readable and writable until the returned guard is dropped.
+    pub fn write(&mut self) -> WriteGuard<'_, [T]> {
+        self.protection.set(Protection::ReadWrite);
+        WriteGuard { protection: &self.protection, value: self.content.unsecure_mut() }
+    }
+
+    /// Borrow the contents of the vector for the duration of `f`.
+    pub fn with_unsecure<R>(&self, f: impl FnOnce(&[T]) -> R) -> R {
+        f(&self.read())
+    }
+
+    /// Mutably borrow the contents of the vector for the duration of `f`.
+    pub fn with_unsecure_mut<R>(&mut self, f: impl FnOnce(&mut [T]) -> R) -> R {
+        f(&mut self.write())
+    }
+}
+
+impl<T> From<SecureVec<T, SecureAllocator>> for ProtectedVec<T>
+where
+    T: Copy + Zeroize,
+{
+    fn from(content: SecureVec<T, SecureAllocator>) -> Self {
+        Self::new(content)
+    }
+}
+
+impl<T> Drop for ProtectedVec<T>
+where
+    T: Copy + Zeroize,
+{
+    fn drop(&mut self) {
+        // The `SecureVec` zeroes its memory on drop
+        self.protection.set(Protection::ReadWrite);
+    }
+}
+
+impl<T> fmt::Debug for ProtectedVec<T>
+where
+    T: Copy + Zeroize,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("***SECRET***").map_err(|_| fmt::Error)
+    }
+}
+
+impl<T> fmt::Display for ProtectedVec<T>
+where
+    T: Copy + Zeroize,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("***SECRET***").map_err(|_| fmt::Error)
+    }
+}
+
+/// A [`SecureBox`] whose memory cannot be accessed at all, unless it is borrowed through a guard.
+///
+/// See [`ProtectedVec`] for details.
+pub struct ProtectedBox<T>
+where
+    T: Copy,
+{
+    content: SecureBox<T, SecureAllocator>,
+    protection: PageProtection,
+}
+
+impl<T> ProtectedBox<T>
+where
+    T: Copy,
+{
+    pub fn new(content: SecureBox<T, SecureAllocator>) -> Self {
+        let protection = PageProtection::new(content.unsecure() as *const T as *const u8, std::mem::size_of::<T>());
+        Self { content, protection }
+    }
+
+    /// Returns `true` if the memory of the box is locked into RAM, such that it cannot be swapped to disk.
+    pub fn is_locked(&self) -> bool {
+        self.content.is_locked()
+    }
+
+    /// Make the contents readable until the returned guard and all other read guards are dropped.
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        self.protection.acquire_read();
+        ReadGuard { protection: &self.protection, value: self.content.unsecure() }
+    }
+
+    /// Make the contents readable and writable until the returned guard is dropped.
+    pub fn write(&mut self) -> WriteGuard<'_, T> {
+        self.protection.set(Protection::ReadWrite);
+        WriteGuard { protection: &self.protection, value: self.content.unsecure_mut() }
+    }
+
+    /// Borrow the contents of the box for the duration of `f`.
+    pub fn with_unsecure<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.read())
+    }
+
+    /// Mutably borrow the contents of the box for the duration of `f`.
+    pub fn with_unsecure_mut<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.write())
+    }
+}
+
+impl<T> From<SecureBox<T, SecureAllocator>> for ProtectedBox<T>
+where
+    T: Copy,
+{
+    fn from(content: SecureBox<T, SecureAllocator>) -> Self {
+        Self::new(content)
+    }
+}
+
+impl<T> Drop for ProtectedBox<T>
+where
+    T: Copy,
+{
+    fn drop(&mut self) {
+        // The `SecureBox` zeroes its memory on drop
+        self.protection.set(Protection::ReadWrite);
+    }
+}
+
+impl<T> fmt::Debug for ProtectedBox<T>
+where
+    T: Copy,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("***SECRET***").map_err(|_| fmt::Error)
+    }
+}
+
+impl<T> fmt::Display for ProtectedBox<T>
+where
+    T: Copy,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("***SECRET***").map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use allocator_api2::boxed::Box;
+
+    use super::{ProtectedBox, ProtectedVec};
+    use crate::{SecureAllocator, SecureBox};
+
+    #[test]
+    fn test_vec() {
+        let mut secret = ProtectedVec::from_slice(b"hello");
+        assert!(secret.is_locked());
+        assert_eq!(&*secret.read(), b"hello");
+        {
+            let first = secret.read();
+            let second = secret.read();
+            assert_eq!(*first, *second);
+        }
+        secret.write()[0] = b'j';
+        assert!(secret.with_unsecure(|bytes| bytes == b"jello"));
+        secret.with_unsecure_mut(|bytes| bytes.copy_from_slice(b"yolo!"));
+        assert_eq!(&*secret.read(), b"yolo!");
+        assert_eq!(format!("{secret:?}"), "***SECRET***");
+
+        let empty = ProtectedVec::<u8>::from_slice(&[]);
+        assert!(empty.read().is_empty());
+    }
+
+    #[test]
+    fn test_box() {
+        let mut key = ProtectedBox::new(SecureBox::new_in(Box::new_in([1u64, 2, 3, 4], SecureAllocator::new())));
+        assert_eq!(*key.read(), [1, 2, 3, 4]);
+        key.write()[3] = 5;
+        assert_eq!(key.with_unsecure(|key| key[3]), 5);
+    }
+
+    /// Returns `true` if `f` makes a forked child process crash.
+    #[cfg(unix)]
+    fn crashes(f: impl FnOnce()) -> bool {
+        unsafe {
+            match libc::fork() {
+                0 => {
+                    f();
+                    libc::_exit(0);
+                },
+                -1 => panic!("fork failed"),
+                child => {
+                    let mut status = 0;
+                    libc::waitpid(child, &mut status, 0);
+                    libc::WIFSIGNALED(status)
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_no_access_while_idle() {
+        let secret = ProtectedVec::from_slice(b"hello");
+        let ptr = secret.read().as_ptr();
+        assert!(!crashes(|| {
+            let guard = secret.read();
+            std::hint::black_box(unsafe { std::ptr::read_volatile(ptr) });
+            drop(guard);
+        }));
+        assert!(crashes(|| {
+            std::hint::black_box(unsafe { std::ptr::read_volatile(ptr) });
+        }));
+        assert!(crashes(|| {
+            let _guard = secret.read();
+            unsafe { std::ptr::write_volatile(ptr as *mut u8, 0) };
+        }));
+    }
+}
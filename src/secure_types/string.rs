@@ -1,34 +1,150 @@
 use core::fmt;
-use std::str::FromStr;
+use std::{
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
 
-use crate::{secure_utils::memlock, SecureVec};
+use allocator_api2::{
+    alloc::{Allocator, Global},
+    vec::Vec,
+};
+use zeroize::Zeroize;
+
+use crate::{
+    secure_types::vec::into_std_vec,
+    secure_utils::{lock_policy::LockError, memlock},
+    SecureVec,
+};
 
 /// Wrapper for a vector that stores a valid UTF-8 string
-#[derive(Clone, Eq)]
-pub struct SecureString(SecureVec<u8>);
+pub struct SecureString<A: Allocator = Global>(pub(crate) SecureVec<u8, A>);
 
 impl SecureString {
-    /// Borrow the contents of the string.
+    /// Create an empty `SecureString` that can hold `capacity` bytes without reallocating.
+    ///
+    /// Use this to build a string incrementally, e.g. with [`SecureString::push`].
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+
+    /// Like [`SecureString::from`], but returns an error if the memory cannot be locked, regardless of the
+    /// [lock policy](crate::set_lock_policy). The contents are zeroed before the error is returned.
+    pub fn try_from_string(s: String) -> Result<Self, LockError> {
+        Ok(SecureString(SecureVec::try_new(s.into_bytes())?))
+    }
+
+    /// Turn the string into a regular `String` again.
     #[cfg_attr(feature = "pre", pre::pre)]
-    pub fn unsecure(&self) -> &str {
+    pub fn into_unsecure(mut self) -> String {
+        if self.0.is_locked() {
+            memlock::munlock(self.0.content.as_mut_ptr(), self.0.content.capacity());
+        }
+        memlock::set_wipe_on_fork(self.0.content.as_mut_ptr(), self.0.content.capacity(), false);
+        let content = into_std_vec(std::mem::take(&mut self.0.content));
+        std::mem::forget(self);
         #[cfg_attr(
             feature = "pre",
-            forward(pre),
+            forward(impl pre::std::string::String),
             assure(
-                "the content of `v` is valid UTF-8",
+                "the content of `bytes` is valid UTF-8",
                 reason = "it is not possible to create a `SecureString` with invalid UTF-8 content
                 and it is also not possible to modify the content as non-UTF-8 directly, so
                 they must still be valid UTF-8 here"
             )
         )]
         unsafe {
-            std::str::from_utf8_unchecked(self.0.unsecure())
+            String::from_utf8_unchecked(content)
         }
     }
+}
 
-    /// Mutably borrow the contents of the string.
+impl<A: Allocator> SecureString<A> {
+    /// Copy `s` into a new `SecureString` that is allocated with `alloc`, e.g. [`SecureAllocator`](crate::SecureAllocator).
+    pub fn from_str_in(s: &str, alloc: A) -> Self {
+        SecureString(SecureVec::from_slice_in(s.as_bytes(), alloc))
+    }
+
+    /// Like [`SecureString::with_capacity`], but allocated with `alloc`.
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        SecureString(SecureVec::new_in(Vec::with_capacity_in(capacity, alloc)))
+    }
+
+    /// Returns the length of the string in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the number of bytes the string can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
+    /// Removes the last character from the string and returns it, or `None` if it is empty.
+    ///
+    /// The memory of the removed character is zeroed.
+    pub fn pop(&mut self) -> Option<char> {
+        let ch = self.unsecure().chars().next_back()?;
+        self.0.truncate(self.len() - ch.len_utf8());
+        Some(ch)
+    }
+
+    /// Removes and returns the character at byte position `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not at a character boundary or not smaller than the length of the string.
+    pub fn remove(&mut self, index: usize) -> char {
+        let ch = self.unsecure()[index..]
+            .chars()
+            .next()
+            .expect("cannot remove a char from the end of a string");
+        let next = index + ch.len_utf8();
+        let len = self.len();
+        self.0.unsecure_mut().copy_within(next..len, index);
+        // Zeroes the stale bytes after the new end
+        self.0.truncate(len - (next - index));
+        ch
+    }
+
+    /// Shortens the string to `new_len` bytes, or does nothing if it is not longer than that.
+    ///
+    /// The memory of the removed characters is zeroed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `new_len` is not at a character boundary.
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len <= self.len() {
+            assert!(self.unsecure().is_char_boundary(new_len), "new_len is not at a char boundary");
+            self.0.truncate(new_len);
+        }
+    }
+
+    /// Removes all characters from the string, without changing its capacity.
+    ///
+    /// The memory of the removed characters is zeroed.
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// Returns `true` if the memory of the string is locked into RAM, such that it cannot be swapped to disk.
+    pub fn is_locked(&self) -> bool {
+        self.0.is_locked()
+    }
+
+    /// Let child processes created with `fork` inherit the contents of the string, see [`SecureVec::keep_on_fork`].
+    pub fn keep_on_fork(&mut self) {
+        self.0.keep_on_fork()
+    }
+
+    /// Borrow the contents of the string.
     #[cfg_attr(feature = "pre", pre::pre)]
-    pub fn unsecure_mut(&mut self) -> &mut str {
+    pub fn unsecure(&self) -> &str {
         #[cfg_attr(
             feature = "pre",
             forward(pre),
@@ -40,31 +156,36 @@ impl SecureString {
             )
         )]
         unsafe {
-            std::str::from_utf8_unchecked_mut(self.0.unsecure_mut())
+            std::str::from_utf8_unchecked(self.0.unsecure())
         }
     }
 
-    /// Turn the string into a regular `String` again.
+    /// Mutably borrow the contents of the string.
     #[cfg_attr(feature = "pre", pre::pre)]
-    pub fn into_unsecure(mut self) -> String {
-        memlock::munlock(self.0.content.as_mut_ptr(), self.0.content.capacity());
-        let content = std::mem::take(&mut self.0.content);
-        std::mem::forget(self);
+    pub fn unsecure_mut(&mut self) -> &mut str {
         #[cfg_attr(
             feature = "pre",
-            forward(impl pre::std::string::String),
+            forward(pre),
             assure(
-                "the content of `bytes` is valid UTF-8",
+                "the content of `v` is valid UTF-8",
                 reason = "it is not possible to create a `SecureString` with invalid UTF-8 content
                 and it is also not possible to modify the content as non-UTF-8 directly, so
                 they must still be valid UTF-8 here"
             )
         )]
         unsafe {
-            String::from_utf8_unchecked(content)
+            std::str::from_utf8_unchecked_mut(self.0.unsecure_mut())
         }
     }
 
+    /// Compare with `other` in time that depends only on `max_length`, such that neither length is leaked.
+    ///
+    /// Strings longer than `max_length` bytes are never equal to anything.
+    /// Choose `max_length` as an upper bound on all valid values, e.g. the maximum password length.
+    pub fn ct_eq_padded(&self, other: &SecureString<A>, max_length: usize) -> bool {
+        self.0.ct_eq_padded(&other.0, max_length)
+    }
+
     /// Overwrite the string with zeros. This is automatically called in the destructor.
     ///
     /// This also sets the length to `0`.
@@ -73,20 +194,65 @@ impl SecureString {
     }
 }
 
-impl PartialEq for SecureString {
-    fn eq(&self, other: &SecureString) -> bool {
-        // use implementation of SecureVec
+impl<A: Allocator + Clone> SecureString<A> {
+    /// Appends the character `ch` to the end of the string.
+    ///
+    /// If the capacity grows, the contents are moved to a new secured memory region, see [`SecureVec::reserve`].
+    pub fn push(&mut self, ch: char) {
+        let mut buffer = [0; 4];
+        self.push_str(ch.encode_utf8(&mut buffer));
+        buffer.zeroize();
+    }
+
+    /// Appends `s` to the end of the string.
+    ///
+    /// If the capacity grows, the contents are moved to a new secured memory region, see [`SecureVec::reserve`].
+    pub fn push_str(&mut self, s: &str) {
+        self.0.extend_from_slice(s.as_bytes())
+    }
+
+    /// Inserts the character `ch` at byte position `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not at a character boundary or larger than the length of the string.
+    pub fn insert(&mut self, index: usize, ch: char) {
+        assert!(self.unsecure().is_char_boundary(index), "index is not at a char boundary");
+        let len = ch.len_utf8();
+        self.push(ch);
+        self.0.unsecure_mut()[index..].rotate_right(len);
+    }
+}
+
+impl<A: Allocator + Clone> Clone for SecureString<A> {
+    fn clone(&self) -> Self {
+        SecureString(self.0.clone())
+    }
+}
+
+impl<A: Allocator> PartialEq for SecureString<A> {
+    fn eq(&self, other: &SecureString<A>) -> bool {
+        // use the constant time implementation of SecureVec
         self.0 == other.0
     }
 }
 
-impl fmt::Debug for SecureString {
+impl<A: Allocator> Eq for SecureString<A> {}
+
+impl<A: Allocator> Hash for SecureString<A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // use the keyed implementation of SecureVec
+        self.0.hash(state)
+    }
+}
+
+impl<A: Allocator> fmt::Debug for SecureString<A> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str("***SECRET***").map_err(|_| fmt::Error)
     }
 }
 
-impl fmt::Display for SecureString {
+impl<A: Allocator> fmt::Display for SecureString<A> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str("***SECRET***").map_err(|_| fmt::Error)
     }
@@ -109,35 +275,57 @@ impl FromStr for SecureString {
     }
 }
 
-#[cfg(feature = "serde")]
-impl serde::Serialize for SecureString {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str(self.unsecure())
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::SecureString;
+    use crate::SecureAllocator;
 
-#[cfg(feature = "serde")]
-impl<'de> serde::Deserialize<'de> for SecureString {
-    fn deserialize<D>(deserializer: D) -> Result<SecureString, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        struct SecureStringVisitor;
-        impl<'de> serde::de::Visitor<'de> for SecureStringVisitor {
-            type Value = SecureString;
-            fn expecting(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-                write!(formatter, "an utf-8 encoded string")
-            }
-            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-            where
-                E: serde::de::Error,
-            {
-                Ok(SecureString::from(v.to_string()))
-            }
+    #[test]
+    fn test_editing() {
+        let mut passphrase = SecureString::with_capacity(4);
+        assert!(passphrase.is_empty());
+        for ch in "correct horse".chars() {
+            passphrase.push(ch);
         }
-        deserializer.deserialize_string(SecureStringVisitor)
+        passphrase.push_str(" 🦄 staple");
+        assert!(passphrase.is_locked());
+        assert_eq!(passphrase.unsecure(), "correct horse 🦄 staple");
+
+        passphrase.insert(0, 'ß');
+        assert_eq!(passphrase.remove(16), '🦄');
+        assert_eq!(passphrase.pop(), Some('e'));
+        assert_eq!(passphrase.unsecure(), "ßcorrect horse  stapl");
+        passphrase.truncate(2);
+        assert_eq!(passphrase.unsecure(), "ß");
+        assert_eq!(passphrase.len(), 2);
+        assert!(passphrase.capacity() >= 2);
+        assert_eq!(passphrase.pop(), Some('ß'));
+        assert_eq!(passphrase.pop(), None);
+
+        passphrase.push_str("hunter2");
+        passphrase.clear();
+        assert_eq!(passphrase.unsecure(), "");
+    }
+
+    #[test]
+    fn test_editing_zeroes() {
+        let mut passphrase = SecureString::from_str_in("correct horse", SecureAllocator::new());
+        passphrase.remove(0);
+        passphrase.pop();
+        passphrase.truncate(5);
+        assert_eq!(passphrase.unsecure(), "orrec");
+        // Safety: all bytes of the spare capacity were initialized before they were removed
+        assert!(passphrase
+            .0
+            .content
+            .spare_capacity_mut()
+            .iter()
+            .all(|byte| unsafe { byte.assume_init() } == 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "char boundary")]
+    fn test_truncate_inside_char() {
+        SecureString::from("🦄").truncate(1);
     }
 }
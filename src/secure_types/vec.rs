@@ -1,43 +1,171 @@
 use core::fmt;
 use std::{
     borrow::{Borrow, BorrowMut},
+    hash::{Hash, Hasher},
+    ops::RangeBounds,
     str::FromStr,
 };
 
+use allocator_api2::{
+    alloc::{Allocator, Global},
+    vec::Vec,
+};
 use zeroize::Zeroize;
 
-use crate::secure_utils::memlock;
+use crate::secure_utils::{
+    constant_time, keyed_hash,
+    lock_policy::{self, LockError},
+    memlock,
+};
 
 /// A data type suitable for storing sensitive information such as passwords and private keys in memory, that implements:
 ///
 /// - Automatic zeroing in `Drop`
 /// - Constant time comparison in `PartialEq` (does not short circuit on the first different character; but terminates instantly if strings have different length)
+/// - Comparison that also hides the length in `SecureBytes::ct_eq_padded`
+/// - Hashing only a keyed digest in `Hash`, such that secrets can be used as keys in maps
 /// - Outputting `***SECRET***` to prevent leaking secrets into logs in `fmt::Debug` and `fmt::Display`
 /// - Automatic `mlock` to protect against leaking into swap (any unix)
 /// - Automatic `madvise(MADV_NOCORE/MADV_DONTDUMP)` to protect against leaking into core dumps (FreeBSD, DragonflyBSD, Linux)
+/// - Automatic `madvise(MADV_WIPEONFORK)`/`minherit(INHERIT_ZERO)` to protect against leaking into child processes
+///   created with `fork`, for the pages that are occupied only by the vector (any unix, see [`SecureVec::keep_on_fork`])
+/// - Optionally storing the contents on dedicated pages with guard pages, using [`SecureAllocator`](crate::SecureAllocator)
 ///
 /// Comparisons using the `PartialEq` implementation are undefined behavior (and most likely wrong) if `T` has any padding bytes.
 ///
+/// `PartialOrd` and `Ord` are only implemented with the `insecure-ord` feature, since they are not constant time.
+///
 /// Be careful with `SecureBytes::from`: if you have a borrowed string, it will be copied.
 /// Use `SecureBytes::new` if you have a `Vec<u8>`.
-#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct SecureVec<T>
+pub struct SecureVec<T, A = Global>
 where
     T: Copy + Zeroize,
+    A: Allocator,
 {
-    pub(crate) content: Vec<T>,
+    pub(crate) content: Vec<T, A>,
+    locked: bool,
+    wipe_on_fork: bool,
 }
 
 /// Type alias for a vector that stores just bytes
-pub type SecureBytes = SecureVec<u8>;
+pub type SecureBytes<A = Global> = SecureVec<u8, A>;
 
 impl<T> SecureVec<T>
 where
     T: Copy + Zeroize,
 {
-    pub fn new(mut cont: Vec<T>) -> Self {
-        memlock::mlock(cont.as_mut_ptr(), cont.capacity());
-        SecureVec { content: cont }
+    pub fn new(cont: std::vec::Vec<T>) -> Self {
+        Self::new_in(from_std_vec(cont))
+    }
+
+    /// Like [`SecureVec::new`], but returns an error if the memory cannot be locked, regardless of the
+    /// [lock policy](crate::set_lock_policy). The contents are zeroed before the error is returned.
+    pub fn try_new(cont: std::vec::Vec<T>) -> Result<Self, LockError> {
+        Self::try_new_in(from_std_vec(cont))
+    }
+}
+
+impl<T, A> SecureVec<T, A>
+where
+    T: Copy + Zeroize,
+    A: Allocator,
+{
+    /// Create a `SecureVec` from a vector with a custom allocator, e.g. [`SecureAllocator`](crate::SecureAllocator).
+    pub fn new_in(cont: Vec<T, A>) -> Self {
+        // Locking may panic depending on the lock policy, which drops the vector and thereby zeroes its contents
+        let mut result = SecureVec { content: cont, locked: false, wipe_on_fork: false };
+        result.locked = lock_policy::lock(result.content.as_mut_ptr(), result.content.capacity());
+        memlock::set_wipe_on_fork(result.content.as_mut_ptr(), result.content.capacity(), true);
+        result.wipe_on_fork = true;
+        result
+    }
+
+    /// Like [`SecureVec::new_in`], but returns an error if the memory cannot be locked, regardless of the
+    /// [lock policy](crate::set_lock_policy). The contents are zeroed before the error is returned.
+    pub fn try_new_in(cont: Vec<T, A>) -> Result<Self, LockError> {
+        // Dropping the unlocked vector on errors zeroes its contents
+        let mut result = SecureVec { content: cont, locked: false, wipe_on_fork: false };
+        memlock::mlock(result.content.as_mut_ptr(), result.content.capacity())?;
+        result.locked = true;
+        memlock::set_wipe_on_fork(result.content.as_mut_ptr(), result.content.capacity(), true);
+        result.wipe_on_fork = true;
+        Ok(result)
+    }
+
+    /// Copy `slice` into a new `SecureVec` that is allocated with `alloc`.
+    ///
+    /// The memory is locked before the contents are copied into it.
+    pub fn from_slice_in(slice: &[T], alloc: A) -> Self {
+        let mut result = Self::new_in(Vec::with_capacity_in(slice.len(), alloc));
+        result.content.extend_from_slice(slice);
+        result
+    }
+
+    /// Returns `true` if the memory of the vector is locked into RAM, such that it cannot be swapped to disk.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Returns the number of elements in the vector.
+    pub fn len(&self) -> usize {
+        self.content.len()
+    }
+
+    /// Returns `true` if the vector contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
+
+    /// Returns the number of elements the vector can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.content.capacity()
+    }
+
+    /// Removes the last element from the vector and returns it, or `None` if it is empty.
+    ///
+    /// The memory of the removed element is zeroed.
+    pub fn pop(&mut self) -> Option<T> {
+        let value = self.content.pop()?;
+        self.zero_removed(1);
+        Some(value)
+    }
+
+    /// Removes and returns the element at `index`, shifting all elements after it to the left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let value = self.content.remove(index);
+        // The last element was shifted to the left, but is still present after the end
+        self.zero_removed(1);
+        value
+    }
+
+    /// Shortens the vector to `len` elements, or does nothing if it is not longer than that.
+    ///
+    /// The memory of the removed elements is zeroed.
+    pub fn truncate(&mut self, len: usize) {
+        let removed = self.content.len().saturating_sub(len);
+        self.content.truncate(len);
+        self.zero_removed(removed);
+    }
+
+    /// Removes all elements from the vector, without changing its capacity.
+    ///
+    /// The memory of the removed elements is zeroed.
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    /// Let child processes created with `fork` inherit the contents of the vector.
+    ///
+    /// By default, the pages that are occupied only by the vector appear zeroed in child processes. Pages that are
+    /// shared with other memory are always inherited, so small vectors should use
+    /// [`SecureAllocator`](crate::SecureAllocator) to be wiped reliably.
+    pub fn keep_on_fork(&mut self) {
+        memlock::set_wipe_on_fork(self.content.as_mut_ptr(), self.content.capacity(), false);
+        self.wipe_on_fork = false;
     }
 
     /// Borrow the contents of the string.
@@ -50,9 +178,30 @@ where
         self.borrow_mut()
     }
 
+    /// Zero the memory of the `count` elements right after the end of the vector, which were just removed from it.
+    fn zero_removed(&mut self, count: usize) {
+        self.content.spare_capacity_mut()[..count].zeroize();
+    }
+
+    /// Overwrite the string with zeros. This is automatically called in the destructor.
+    ///
+    /// This also sets the length to `0`.
+    pub fn zero_out(&mut self) {
+        // The same as `Zeroize` does for `std::vec::Vec`
+        self.content.iter_mut().zeroize();
+        self.content.clear();
+        self.content.spare_capacity_mut().zeroize();
+    }
+}
+
+impl<T, A> SecureVec<T, A>
+where
+    T: Copy + Zeroize,
+    A: Allocator + Clone,
+{
     /// Resizes the `SecureVec` in-place so that len is equal to `new_len`.
     ///
-    /// If `new_len` is smaller the inner vector is truncated.
+    /// If `new_len` is smaller the inner vector is truncated, and the memory of the removed elements is zeroed.
     /// If `new_len` is larger the inner vector will grow, placing `value` in all new cells.
     ///
     /// This ensures that the new memory region is secured if reallocation occurs.
@@ -61,39 +210,171 @@ where
     pub fn resize(&mut self, new_len: usize, value: T) {
         // Trucnate if shorter or same length
         if new_len <= self.content.len() {
-            self.content.truncate(new_len);
+            self.truncate(new_len);
+            return;
+        }
+
+        self.reserve(new_len - self.content.len());
+        self.content.resize(new_len, value);
+    }
+
+    /// Reserves capacity for at least `additional` more elements.
+    ///
+    /// If the capacity grows, the contents are moved to a new secured memory region, and the old one is zeroed before
+    /// it is freed. Like `Vec`, the capacity grows at least by a factor of two, such that repeated pushes are cheap.
+    pub fn reserve(&mut self, additional: usize) {
+        if self.content.capacity() - self.content.len() >= additional {
             return;
         }
+        let required = self.content.len().checked_add(additional).expect("capacity overflow");
+        self.reallocate(required.max(self.content.capacity().saturating_mul(2)));
+    }
+
+    /// Shrinks the capacity of the vector as much as possible, moving the contents to a new secured memory region.
+    pub fn shrink_to_fit(&mut self) {
+        if self.content.capacity() > self.content.len() {
+            self.reallocate(self.content.len());
+        }
+    }
+
+    /// Appends an element to the back of the vector.
+    pub fn push(&mut self, value: T) {
+        self.reserve(1);
+        self.content.push(value);
+    }
+
+    /// Appends all elements of `other` to the vector.
+    pub fn extend_from_slice(&mut self, other: &[T]) {
+        self.reserve(other.len());
+        self.content.extend_from_slice(other);
+    }
+
+    /// Moves all elements of `other` into the vector, leaving `other` empty and zeroed.
+    pub fn append(&mut self, other: &mut Self) {
+        self.extend_from_slice(other.unsecure());
+        other.zero_out();
+    }
+
+    /// Inserts an element at `index`, shifting all elements after it to the right.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(
+            index <= self.content.len(),
+            "insertion index (is {index}) should be <= len (is {})",
+            self.content.len()
+        );
+        self.reserve(1);
+        self.content.insert(index, value);
+    }
 
+    /// Removes the elements in `range` from the vector and returns them.
+    ///
+    /// Unlike `Vec::drain`, the removed elements are returned in a new `SecureVec` that uses the same allocator,
+    /// such that they stay protected. Their memory in this vector is zeroed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> Self {
+        let alloc = self.content.allocator().clone();
+        let drain = self.content.drain(range);
+        let mut drained = Self::new_in(Vec::with_capacity_in(drain.len(), alloc));
+        drained.content.extend(drain);
+        // The tail was shifted to the left, leaving stale copies after the end
+        self.zero_removed(drained.len());
+        drained
+    }
+
+    /// Move the contents to a new secured memory region with the given capacity, and zero and unlock the old one.
+    ///
+    /// This is the only way the capacity may change, since reallocating the inner vector directly would free the old
+    /// memory without zeroing it.
+    fn reallocate(&mut self, capacity: usize) {
         // Allocate new vector, copy old data into it
-        let mut new_vec = vec![value; new_len];
-        memlock::mlock(new_vec.as_mut_ptr(), new_vec.capacity());
-        new_vec[0..self.content.len()].copy_from_slice(&self.content);
+        let mut new_vec = Vec::with_capacity_in(capacity, self.content.allocator().clone());
+        let locked = lock_policy::lock(new_vec.as_mut_ptr(), new_vec.capacity());
+        memlock::set_wipe_on_fork(new_vec.as_mut_ptr(), new_vec.capacity(), self.wipe_on_fork);
+        new_vec.extend_from_slice(&self.content);
 
         // Securely clear old vector, replace with new vector
         self.zero_out();
-        memlock::munlock(self.content.as_mut_ptr(), self.content.capacity());
+        if self.locked {
+            memlock::munlock(self.content.as_mut_ptr(), self.content.capacity());
+        }
+        if self.wipe_on_fork {
+            memlock::set_wipe_on_fork(self.content.as_mut_ptr(), self.content.capacity(), false);
+        }
         self.content = new_vec;
+        self.locked = locked;
     }
+}
 
-    /// Overwrite the string with zeros. This is automatically called in the destructor.
+impl<A: Allocator> SecureVec<u8, A> {
+    /// Compare with `other` in time that depends only on `max_length`, such that neither length is leaked.
     ///
-    /// This also sets the length to `0`.
-    pub fn zero_out(&mut self) {
-        self.content.zeroize()
+    /// Values longer than `max_length` bytes are never equal to anything.
+    /// Choose `max_length` as an upper bound on all valid values, e.g. the maximum password length.
+    pub fn ct_eq_padded(&self, other: &Self, max_length: usize) -> bool {
+        constant_time::eq_padded(self.unsecure(), other.unsecure(), max_length)
     }
 }
 
-impl<T: Copy + Zeroize> Clone for SecureVec<T> {
+/// Convert a vector of the standard library into one of `allocator_api2`, without copying.
+pub(crate) fn from_std_vec<T>(vec: std::vec::Vec<T>) -> Vec<T> {
+    let mut vec = std::mem::ManuallyDrop::new(vec);
+    // Safety: `Global` is the global allocator, which allocated the standard library vector
+    unsafe { Vec::from_raw_parts(vec.as_mut_ptr(), vec.len(), vec.capacity()) }
+}
+
+/// Convert a vector of `allocator_api2` into one of the standard library, without copying.
+pub(crate) fn into_std_vec<T>(vec: Vec<T>) -> std::vec::Vec<T> {
+    let (ptr, length, capacity) = vec.into_raw_parts();
+    // Safety: `Global` is the global allocator, which is used by the standard library vector
+    unsafe { std::vec::Vec::from_raw_parts(ptr, length, capacity) }
+}
+
+impl<T: Copy + Zeroize, A: Allocator + Clone> Clone for SecureVec<T, A> {
     fn clone(&self) -> Self {
-        Self::new(self.content.clone())
+        Self::from_slice_in(self.unsecure(), self.content.allocator().clone())
+    }
+}
+
+impl<T: Copy + Zeroize, A: Allocator> PartialEq for SecureVec<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time::eq(self.unsecure(), other.unsecure())
+    }
+}
+
+impl<T: Copy + Zeroize, A: Allocator> Eq for SecureVec<T, A> {}
+
+#[cfg(feature = "insecure-ord")]
+impl<T: Copy + Zeroize + PartialOrd, A: Allocator> PartialOrd for SecureVec<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.unsecure().partial_cmp(other.unsecure())
+    }
+}
+
+#[cfg(feature = "insecure-ord")]
+impl<T: Copy + Zeroize + Ord, A: Allocator> Ord for SecureVec<T, A> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.unsecure().cmp(other.unsecure())
+    }
+}
+
+// Only the keyed digest is passed to the hasher, such that it cannot observe the secret itself
+impl<T: Copy + Zeroize, A: Allocator> Hash for SecureVec<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(keyed_hash::digest(constant_time::as_bytes(self.unsecure())))
     }
 }
 
 // Creation
 impl<T, U> From<U> for SecureVec<T>
 where
-    U: Into<Vec<T>>,
+    U: Into<std::vec::Vec<T>>,
     T: Copy + Zeroize,
 {
     fn from(s: U) -> SecureVec<T> {
@@ -110,12 +391,13 @@ impl FromStr for SecureVec<u8> {
 }
 
 // Vec item indexing
-impl<T, U> std::ops::Index<U> for SecureVec<T>
+impl<T, U, A> std::ops::Index<U> for SecureVec<T, A>
 where
     T: Copy + Zeroize,
-    Vec<T>: std::ops::Index<U>,
+    A: Allocator,
+    Vec<T, A>: std::ops::Index<U>,
 {
-    type Output = <Vec<T> as std::ops::Index<U>>::Output;
+    type Output = <Vec<T, A> as std::ops::Index<U>>::Output;
 
     fn index(&self, index: U) -> &Self::Output {
         std::ops::Index::index(&self.content, index)
@@ -123,48 +405,58 @@ where
 }
 
 // Borrowing
-impl<T> Borrow<[T]> for SecureVec<T>
+impl<T, A> Borrow<[T]> for SecureVec<T, A>
 where
     T: Copy + Zeroize,
+    A: Allocator,
 {
     fn borrow(&self) -> &[T] {
-        self.content.borrow()
+        self.content.as_slice()
     }
 }
 
-impl<T> BorrowMut<[T]> for SecureVec<T>
+impl<T, A> BorrowMut<[T]> for SecureVec<T, A>
 where
     T: Copy + Zeroize,
+    A: Allocator,
 {
     fn borrow_mut(&mut self) -> &mut [T] {
-        self.content.borrow_mut()
+        self.content.as_mut_slice()
     }
 }
 
 // Overwrite memory with zeros when we're done
-impl<T> Drop for SecureVec<T>
+impl<T, A> Drop for SecureVec<T, A>
 where
     T: Copy + Zeroize,
+    A: Allocator,
 {
     fn drop(&mut self) {
         self.zero_out();
-        memlock::munlock(self.content.as_mut_ptr(), self.content.capacity());
+        if self.locked {
+            memlock::munlock(self.content.as_mut_ptr(), self.content.capacity());
+        }
+        if self.wipe_on_fork {
+            memlock::set_wipe_on_fork(self.content.as_mut_ptr(), self.content.capacity(), false);
+        }
     }
 }
 
 // Make sure sensitive information is not logged accidentally
-impl<T> fmt::Debug for SecureVec<T>
+impl<T, A> fmt::Debug for SecureVec<T, A>
 where
     T: Copy + Zeroize,
+    A: Allocator,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str("***SECRET***").map_err(|_| fmt::Error)
     }
 }
 
-impl<T> fmt::Display for SecureVec<T>
+impl<T, A> fmt::Display for SecureVec<T, A>
 where
     T: Copy + Zeroize,
+    A: Allocator,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str("***SECRET***").map_err(|_| fmt::Error)
@@ -173,8 +465,41 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        alloc::Layout,
+        cell::Cell,
+        collections::HashMap,
+        hash::{Hash, Hasher},
+        ptr::NonNull,
+        rc::Rc,
+    };
+
+    use allocator_api2::alloc::{AllocError, Allocator, Global};
+
     use super::{SecureBytes, SecureVec};
 
+    /// Counts the allocations that are not zeroed when they are freed.
+    #[derive(Clone, Default)]
+    struct ZeroCheckingAllocator {
+        unzeroed_frees: Rc<Cell<usize>>,
+    }
+
+    unsafe impl Allocator for ZeroCheckingAllocator {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            if std::slice::from_raw_parts(ptr.as_ptr(), layout.size())
+                .iter()
+                .any(|&byte| byte != 0)
+            {
+                self.unzeroed_frees.set(self.unzeroed_frees.get() + 1);
+            }
+            Global.deallocate(ptr, layout)
+        }
+    }
+
     #[test]
     fn test_basic() {
         let my_sec = SecureBytes::from("hello");
@@ -207,6 +532,13 @@ mod tests {
         assert_eq!(my_sec.unsecure(), b"\x00\x00\x00\x00\x00");
     }
 
+    #[test]
+    fn test_locked() {
+        assert!(SecureBytes::from("hello").is_locked());
+        assert!(SecureBytes::try_new(b"hello".to_vec()).unwrap().is_locked());
+        assert!(SecureBytes::try_new(Vec::new()).unwrap().is_locked());
+    }
+
     #[test]
     fn test_resize() {
         let mut my_sec = SecureVec::from([0, 1]);
@@ -217,6 +549,86 @@ mod tests {
         assert_eq!(my_sec.unsecure(), &[0, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2]);
     }
 
+    /// Returns `true` if the spare capacity of `vec` contains only zeros.
+    fn spare_capacity_zeroed(vec: &mut SecureBytes) -> bool {
+        // Safety: all elements of the spare capacity were initialized before they were removed
+        vec.content
+            .spare_capacity_mut()
+            .iter()
+            .all(|byte| unsafe { byte.assume_init() } == 0)
+    }
+
+    #[test]
+    fn test_shrinking_zeroes() {
+        let mut my_sec = SecureBytes::from("hello world, hello secret");
+        my_sec.resize(20, 0);
+        assert!(spare_capacity_zeroed(&mut my_sec));
+        my_sec.truncate(18);
+        assert!(spare_capacity_zeroed(&mut my_sec));
+        assert_eq!(my_sec.pop(), Some(b'o'));
+        assert!(spare_capacity_zeroed(&mut my_sec));
+        assert_eq!(my_sec.remove(0), b'h');
+        assert!(spare_capacity_zeroed(&mut my_sec));
+        assert_eq!(my_sec.drain(..5).unsecure(), b"ello ");
+        assert!(spare_capacity_zeroed(&mut my_sec));
+        assert_eq!(my_sec.unsecure(), b"world, hell");
+        my_sec.clear();
+        assert!(spare_capacity_zeroed(&mut my_sec));
+    }
+
+    #[test]
+    fn test_mutation() {
+        let mut my_sec = SecureBytes::from("hello");
+        my_sec.push(b'!');
+        my_sec.extend_from_slice(b" world");
+        assert_eq!(my_sec.unsecure(), b"hello! world");
+        my_sec.insert(0, b'>');
+        assert_eq!(my_sec.remove(6), b'!');
+        assert_eq!(my_sec.pop(), Some(b'd'));
+        assert_eq!(my_sec.unsecure(), b">hello worl");
+
+        let drained = my_sec.drain(1..7);
+        assert_eq!(drained.unsecure(), b"hello ");
+        assert!(drained.is_locked());
+        assert_eq!(my_sec.unsecure(), b">worl");
+
+        let mut other = SecureBytes::from("d!");
+        my_sec.append(&mut other);
+        assert!(other.is_empty());
+        assert_eq!(my_sec.unsecure(), b">world!");
+        my_sec.truncate(6);
+        assert_eq!(my_sec.len(), 6);
+
+        my_sec.reserve(100);
+        assert!(my_sec.capacity() >= 106);
+        my_sec.shrink_to_fit();
+        assert_eq!(my_sec.capacity(), 6);
+        assert_eq!(my_sec.unsecure(), b">world");
+        assert!(my_sec.is_locked());
+
+        my_sec.clear();
+        assert!(my_sec.is_empty());
+        assert_eq!(my_sec.pop(), None);
+    }
+
+    #[test]
+    fn test_reallocation_zeroes() {
+        let alloc = ZeroCheckingAllocator::default();
+        let mut my_sec = SecureVec::new_in(allocator_api2::vec::Vec::new_in(alloc.clone()));
+        for i in 0..1000u32 {
+            my_sec.push(i);
+        }
+        my_sec.extend_from_slice(&[1; 1000]);
+        my_sec.insert(5, 5);
+        my_sec.resize(5000, 2);
+        drop(my_sec.drain(..100));
+        my_sec.truncate(10);
+        my_sec.shrink_to_fit();
+        assert_eq!(my_sec.capacity(), 10);
+        drop(my_sec);
+        assert_eq!(alloc.unzeroed_frees.get(), 0);
+    }
+
     #[test]
     fn test_comparison() {
         assert_eq!(SecureBytes::from("hello"), SecureBytes::from("hello"));
@@ -226,6 +638,50 @@ mod tests {
         assert!(SecureBytes::from("hello") != SecureBytes::from(""));
     }
 
+    #[test]
+    fn test_comparison_padded() {
+        assert!(SecureBytes::from("hello").ct_eq_padded(&SecureBytes::from("hello"), 64));
+        assert!(!SecureBytes::from("hello").ct_eq_padded(&SecureBytes::from("yolo"), 64));
+        assert!(!SecureBytes::from("hello").ct_eq_padded(&SecureBytes::from("helloworld"), 64));
+        assert!(!SecureBytes::from("hello").ct_eq_padded(&SecureBytes::from("hello"), 4));
+    }
+
+    #[test]
+    fn test_hash() {
+        /// A hasher that records everything it is given
+        #[derive(Default)]
+        struct RecordingHasher(Vec<u8>);
+
+        impl Hasher for RecordingHasher {
+            fn finish(&self) -> u64 {
+                0
+            }
+
+            fn write(&mut self, bytes: &[u8]) {
+                self.0.extend_from_slice(bytes)
+            }
+        }
+
+        let hash = |value: &SecureBytes| {
+            let mut hasher = RecordingHasher::default();
+            value.hash(&mut hasher);
+            hasher.0
+        };
+        let secret = SecureBytes::from("correct horse battery staple");
+        assert_eq!(hash(&secret), hash(&secret.clone()));
+        assert_ne!(hash(&secret), hash(&SecureBytes::from("correct horse battery stapler")));
+        assert!(!hash(&secret)
+            .windows(4)
+            .any(|window| b"correct horse battery staple".windows(4).any(|w| w == window)));
+
+        let mut map = HashMap::new();
+        map.insert(secret.clone(), 1);
+        map.insert(SecureBytes::from("hello"), 2);
+        assert_eq!(map.get(&secret), Some(&1));
+        assert_eq!(map.get(&SecureBytes::from("hello")), Some(&2));
+        assert_eq!(map.get(&SecureBytes::from("yolo")), None);
+    }
+
     #[test]
     fn test_indexing() {
         let string = SecureBytes::from("hello");
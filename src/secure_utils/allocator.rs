@@ -0,0 +1,536 @@
+//! An allocator that places every allocation on its own pages, following the design of libsodium's `sodium_malloc`.
+//!
+//! Each allocation is laid out as follows:
+//!
+//! ```text
+//! | guard page | padding | canary | data | guard page |
+//! ```
+//!
+//! The guard pages are mapped with `PROT_NONE`, so reading or writing past either end of the data faults.
+//! The data is placed at the end of its pages, such that overflows hit the guard page immediately, while underflows
+//! first corrupt the canary, which is checked when the allocation is freed.
+//! No other allocation shares pages with the data, so bugs in neighbouring code cannot read or overwrite it.
+//!
+//! The pages between the guard pages come from the [`Backend`] of the allocator.
+
+use std::{
+    alloc::Layout,
+    fmt,
+    ptr::NonNull,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use allocator_api2::alloc::{AllocError, Allocator};
+
+/// An allocator that surrounds each allocation with inaccessible guard pages and checks a canary before it on free.
+///
+/// Allocations are locked into RAM, excluded from core dumps, appear zeroed in child processes created with `fork` and
+/// are zeroed when they are freed.
+/// Since every allocation occupies at least three pages, this is only suitable for a small number of secrets.
+///
+/// Use it with [`SecureVec::new_in`](crate::SecureVec::new_in), [`SecureBox::new_in`](crate::SecureBox::new_in)
+/// and [`SecureString::from_str_in`](crate::SecureString::from_str_in), or directly as an [`Allocator`].
+///
+/// Guard pages are only supported on unix, on other platforms this falls back to the global allocator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SecureAllocator {
+    // `None` follows the process-wide default backend
+    backend: Option<Backend>,
+}
+
+impl SecureAllocator {
+    /// An allocator that uses the [default backend](set_default_backend) at the time of each allocation.
+    pub const fn new() -> Self {
+        Self { backend: None }
+    }
+
+    /// An allocator that uses `backend` regardless of the [default backend](set_default_backend).
+    pub const fn with_backend(backend: Backend) -> Self {
+        Self { backend: Some(backend) }
+    }
+
+    /// The backend that new allocations actually use, after falling back if the requested one is not supported.
+    pub fn backend(&self) -> Backend {
+        match self.backend.unwrap_or_else(default_backend) {
+            Backend::MemfdSecret if !Backend::MemfdSecret.is_supported() => Backend::Anonymous,
+            backend => backend,
+        }
+    }
+}
+
+/// Where a [`SecureAllocator`] takes the pages for its allocations from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum Backend {
+    /// Anonymous memory that is locked into RAM with `mlock` according to the [lock policy](crate::set_lock_policy).
+    #[default]
+    Anonymous,
+    /// Memory from `memfd_secret(2)` (Linux 5.14+), which is additionally removed from the kernel's direct map,
+    /// such that even kernel-level memory disclosure bugs cannot read it. The memory is always locked into RAM and
+    /// counts towards `RLIMIT_MEMLOCK`.
+    ///
+    /// Allocations fall back to [`Backend::Anonymous`] if this is not supported, see [`Backend::is_supported`].
+    MemfdSecret,
+}
+
+impl Backend {
+    /// Returns `true` if allocations with this backend are possible on the running system.
+    ///
+    /// For [`Backend::MemfdSecret`], this is checked once by creating a secret memory file.
+    pub fn is_supported(self) -> bool {
+        match self {
+            Backend::Anonymous => true,
+            Backend::MemfdSecret => guarded::memfd_secret_supported(),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backend::Anonymous => f.write_str("mlock'd anonymous memory"),
+            Backend::MemfdSecret => f.write_str("memfd_secret"),
+        }
+    }
+}
+
+static DEFAULT_BACKEND: AtomicU8 = AtomicU8::new(Backend::Anonymous as u8);
+
+/// Set the process-wide backend of allocators created with [`SecureAllocator::new`].
+pub fn set_default_backend(backend: Backend) {
+    DEFAULT_BACKEND.store(backend as u8, Ordering::Relaxed);
+}
+
+/// Get the process-wide backend of allocators created with [`SecureAllocator::new`].
+pub fn default_backend() -> Backend {
+    match DEFAULT_BACKEND.load(Ordering::Relaxed) {
+        0 => Backend::Anonymous,
+        _ => Backend::MemfdSecret,
+    }
+}
+
+/// The protections for secrets that are available on the running system, see [`capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Memory can be locked into RAM with `mlock`, subject to `RLIMIT_MEMLOCK`.
+    pub mlock: bool,
+    /// Memory can be excluded from core dumps with `madvise`.
+    pub exclude_from_core_dumps: bool,
+    /// [`SecureAllocator`] surrounds allocations with guard pages.
+    pub guard_pages: bool,
+    /// [`Backend::MemfdSecret`] is supported.
+    pub memfd_secret: bool,
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let supported = |supported| if supported { "supported" } else { "unsupported" };
+        write!(
+            f,
+            "mlock: {}, exclusion from core dumps: {}, guard pages: {}, memfd_secret: {}",
+            supported(self.mlock),
+            supported(self.exclude_from_core_dumps),
+            supported(self.guard_pages),
+            supported(self.memfd_secret),
+        )
+    }
+}
+
+/// Report which protections for secrets are available on the running system.
+///
+/// `mlock` may still fail at runtime, e.g. when `RLIMIT_MEMLOCK` is exhausted, which is handled according to the
+/// [lock policy](crate::set_lock_policy).
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        mlock: cfg!(unix),
+        exclude_from_core_dumps: cfg!(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly")),
+        guard_pages: cfg!(unix),
+        memfd_secret: Backend::MemfdSecret.is_supported(),
+    }
+}
+
+/// Access permissions for the pages of an allocation, see [`protect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Protection {
+    NoAccess,
+    ReadOnly,
+    ReadWrite,
+}
+
+#[cfg(unix)]
+mod guarded {
+    use std::{
+        alloc::Layout,
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+        ptr::NonNull,
+        sync::OnceLock,
+    };
+
+    use allocator_api2::alloc::AllocError;
+    use zeroize::Zeroize;
+
+    use super::{Backend, Protection};
+    use crate::secure_utils::{
+        constant_time, lock_policy,
+        memlock::{self, page_size},
+    };
+
+    const CANARY_SIZE: usize = 16;
+
+    /// A random value that is chosen once per process, such that an attacker cannot forge it.
+    fn canary() -> &'static [u8; CANARY_SIZE] {
+        static CANARY: OnceLock<[u8; CANARY_SIZE]> = OnceLock::new();
+        CANARY.get_or_init(|| {
+            let mut canary = [0; CANARY_SIZE];
+            for chunk in canary.chunks_mut(8) {
+                chunk.copy_from_slice(&RandomState::new().build_hasher().finish().to_ne_bytes());
+            }
+            canary
+        })
+    }
+
+    /// The pages of a guarded allocation.
+    struct Region {
+        /// Start of the leading guard page.
+        base: usize,
+        /// Size of the accessible pages between the guard pages.
+        unprotected_size: usize,
+    }
+
+    impl Region {
+        fn for_layout(layout: Layout) -> Self {
+            let page_size = page_size();
+            let unprotected_size = (CANARY_SIZE + layout.size() + layout.align() - 1).next_multiple_of(page_size);
+            Self { base: 0, unprotected_size }
+        }
+
+        fn total_size(&self) -> usize {
+            self.unprotected_size + 2 * page_size()
+        }
+
+        fn unprotected_start(&self) -> usize {
+            self.base + page_size()
+        }
+
+        fn unprotected_end(&self) -> usize {
+            self.unprotected_start() + self.unprotected_size
+        }
+
+        /// The data is placed as close to the trailing guard page as its alignment permits.
+        fn data(&self, layout: Layout) -> usize {
+            (self.unprotected_end() - layout.size()) & !(layout.align() - 1)
+        }
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        any(
+            target_arch = "x86",
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "powerpc64",
+            target_arch = "s390x"
+        )
+    ))]
+    mod secretmem {
+        use std::sync::OnceLock;
+
+        fn create() -> Option<libc::c_int> {
+            let fd = unsafe { libc::syscall(libc::SYS_memfd_secret, libc::O_CLOEXEC) } as libc::c_int;
+            (fd >= 0).then_some(fd)
+        }
+
+        pub fn supported() -> bool {
+            static SUPPORTED: OnceLock<bool> = OnceLock::new();
+            *SUPPORTED.get_or_init(|| create().map(|fd| unsafe { libc::close(fd) }).is_some())
+        }
+
+        /// Replace the `size` bytes of mapped memory at `address` with secret memory.
+        ///
+        /// # Safety
+        ///
+        /// The memory must be mapped and not be in use. It may be unmapped if this fails.
+        pub unsafe fn map(address: usize, size: usize) -> bool {
+            let Some(fd) = create() else {
+                return false;
+            };
+            // The mapping keeps the memory alive after the file is closed
+            let mapped = libc::ftruncate(fd, size as libc::off_t) == 0
+                && libc::mmap(
+                    address as *mut libc::c_void,
+                    size,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED | libc::MAP_FIXED,
+                    fd,
+                    0,
+                ) != libc::MAP_FAILED;
+            libc::close(fd);
+            mapped
+        }
+    }
+
+    #[cfg(not(all(
+        target_os = "linux",
+        any(
+            target_arch = "x86",
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "powerpc64",
+            target_arch = "s390x"
+        )
+    )))]
+    mod secretmem {
+        pub fn supported() -> bool {
+            false
+        }
+
+        pub unsafe fn map(_address: usize, _size: usize) -> bool {
+            false
+        }
+    }
+
+    pub use secretmem::supported as memfd_secret_supported;
+
+    pub fn allocate(layout: Layout, backend: Backend) -> Result<NonNull<u8>, AllocError> {
+        // The data is aligned relative to the page, so larger alignments are not possible
+        if layout.align() > page_size() {
+            return Err(AllocError);
+        }
+
+        let mut region = Region::for_layout(layout);
+        unsafe {
+            // Everything starts out as a guard page, and the pages in between are made accessible afterwards
+            let base = libc::mmap(
+                std::ptr::null_mut(),
+                region.total_size(),
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if base == libc::MAP_FAILED {
+                return Err(AllocError);
+            }
+            region.base = base as usize;
+
+            let unprotected = region.unprotected_start();
+            let secret = backend == Backend::MemfdSecret && secretmem::map(unprotected, region.unprotected_size);
+            if !secret
+                && libc::mmap(
+                    unprotected as *mut libc::c_void,
+                    region.unprotected_size,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED,
+                    -1,
+                    0,
+                ) == libc::MAP_FAILED
+            {
+                libc::munmap(base, region.total_size());
+                return Err(AllocError);
+            }
+        }
+
+        lock_policy::lock(region.unprotected_start() as *mut u8, region.unprotected_size);
+        // The allocation owns all of its pages, so they can be wiped in child processes as a whole
+        memlock::set_wipe_on_fork(region.unprotected_start() as *mut u8, region.unprotected_size, true);
+
+        let data = region.data(layout);
+        unsafe { std::ptr::copy_nonoverlapping(canary().as_ptr(), (data - CANARY_SIZE) as *mut u8, CANARY_SIZE) };
+        Ok(NonNull::new(data as *mut u8).unwrap())
+    }
+
+    /// Set the access permissions of all pages touched by `byte_num` bytes starting at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// The bytes must be part of a single allocation by `allocate`, which owns the pages exclusively.
+    pub unsafe fn protect(ptr: *const u8, byte_num: usize, protection: Protection) {
+        let protection = match protection {
+            Protection::NoAccess => libc::PROT_NONE,
+            Protection::ReadOnly => libc::PROT_READ,
+            Protection::ReadWrite => libc::PROT_READ | libc::PROT_WRITE,
+        };
+        let start = ptr as usize & !(page_size() - 1);
+        let end = (ptr as usize + byte_num).next_multiple_of(page_size());
+        if libc::mprotect(start as *mut libc::c_void, end - start, protection) != 0 {
+            // Continuing would either leave the secret accessible or fault on the next access
+            panic!("mprotect failed: {}", std::io::Error::last_os_error());
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `allocate` with the same `layout`, and must not be used afterwards.
+    pub unsafe fn deallocate(ptr: NonNull<u8>, layout: Layout) {
+        let data = ptr.as_ptr() as usize;
+        let mut region = Region::for_layout(layout);
+        // The data ends less than `layout.align()` bytes before the trailing guard page
+        region.base = (data + layout.size()).next_multiple_of(page_size()) - region.unprotected_size - page_size();
+
+        let canary_slice = std::slice::from_raw_parts((data - CANARY_SIZE) as *const u8, CANARY_SIZE);
+        // Child processes see wiped allocations as zeros, including the canary
+        let wiped = memlock::was_wiped_on_fork(data - CANARY_SIZE) && canary_slice.iter().all(|&byte| byte == 0);
+        if !wiped && !constant_time::eq(canary_slice, canary()) {
+            // Something overwrote memory right before the secret, and may have read the secret as well
+            eprintln!("secure-string: canary of a secure allocation was overwritten, aborting");
+            std::process::abort();
+        }
+
+        let unprotected = std::slice::from_raw_parts_mut(region.unprotected_start() as *mut u8, region.unprotected_size);
+        unprotected.zeroize();
+        // Unlocking is reference counted, so this is balanced even if locking failed
+        memlock::munlock(unprotected.as_mut_ptr(), unprotected.len());
+        memlock::set_wipe_on_fork(unprotected.as_mut_ptr(), unprotected.len(), false);
+        libc::munmap(region.base as *mut libc::c_void, region.total_size());
+    }
+}
+
+#[cfg(not(unix))]
+mod guarded {
+    use std::{alloc::Layout, ptr::NonNull};
+
+    use allocator_api2::alloc::AllocError;
+
+    use super::{Backend, Protection};
+
+    pub fn memfd_secret_supported() -> bool {
+        false
+    }
+
+    pub fn allocate(layout: Layout, _backend: Backend) -> Result<NonNull<u8>, AllocError> {
+        NonNull::new(unsafe { std::alloc::alloc(layout) }).ok_or(AllocError)
+    }
+
+    pub unsafe fn deallocate(ptr: NonNull<u8>, layout: Layout) {
+        std::alloc::dealloc(ptr.as_ptr(), layout)
+    }
+
+    pub unsafe fn protect(_ptr: *const u8, _byte_num: usize, _protection: Protection) {}
+}
+
+pub(crate) use guarded::protect;
+
+unsafe impl Allocator for SecureAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // Zero-sized allocations must not touch memory, so they need no pages either
+        let ptr = if layout.size() == 0 {
+            NonNull::new(layout.align() as *mut u8).unwrap()
+        } else {
+            guarded::allocate(layout, self.backend())?
+        };
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            guarded::deallocate(ptr, layout)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::Layout;
+
+    use allocator_api2::{alloc::Allocator, boxed::Box};
+
+    use super::{Backend, SecureAllocator};
+    use crate::{SecureBox, SecureString, SecureVec};
+
+    #[test]
+    fn test_allocate() {
+        for (size, align) in [(1, 1), (5, 1), (32, 8), (4096, 16), (10000, 64), (0, 8)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let ptr = SecureAllocator::new().allocate(layout).unwrap();
+            assert_eq!(ptr.len(), size);
+            let data = ptr.as_ptr() as *mut u8;
+            assert_eq!(data as usize % align, 0);
+
+            let bytes = unsafe { std::slice::from_raw_parts_mut(data, size) };
+            bytes.fill(0xa5);
+            assert!(bytes.iter().all(|&byte| byte == 0xa5));
+            unsafe { SecureAllocator::new().deallocate(ptr.cast(), layout) };
+        }
+    }
+
+    /// The permissions and path of the mapping containing `address`, as listed in `/proc/self/maps`.
+    #[cfg(target_os = "linux")]
+    fn mapping_at(address: usize) -> (String, String) {
+        let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+        maps.lines()
+            .find_map(|line| {
+                let mut fields = line.split_whitespace();
+                let (start, end) = fields.next()?.split_once('-')?;
+                let range = usize::from_str_radix(start, 16).ok()?..usize::from_str_radix(end, 16).ok()?;
+                let permissions = fields.next().unwrap().to_string();
+                range
+                    .contains(&address)
+                    .then(|| (permissions, fields.nth(3).unwrap_or_default().to_string()))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_guard_pages() {
+        for backend in [Backend::Anonymous, Backend::MemfdSecret] {
+            // The data of this layout fits into a single page, so one page before it is the leading guard page
+            let layout = Layout::from_size_align(100, 4).unwrap();
+            let allocator = SecureAllocator::with_backend(backend);
+            let data = allocator.allocate(layout).unwrap().as_ptr() as *mut u8 as usize;
+
+            let page_size = crate::secure_utils::memlock::page_size();
+            assert!(mapping_at(data).0.starts_with("rw"));
+            assert!(mapping_at(data + 100).0.starts_with("---"));
+            assert!(mapping_at(data - page_size).0.starts_with("---"));
+
+            unsafe { allocator.deallocate(std::ptr::NonNull::new(data as *mut u8).unwrap(), layout) };
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_memfd_secret() {
+        let allocator = SecureAllocator::with_backend(Backend::MemfdSecret);
+        if !Backend::MemfdSecret.is_supported() {
+            assert_eq!(allocator.backend(), Backend::Anonymous);
+            return;
+        }
+        assert_eq!(allocator.backend(), Backend::MemfdSecret);
+
+        let key = SecureBox::new_in(Box::new_in([7u8; 32], allocator));
+        assert_eq!(key.unsecure(), &[7; 32]);
+        assert!(mapping_at(key.unsecure().as_ptr() as usize).1.starts_with("/secretmem"));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = super::capabilities();
+        assert_eq!(capabilities.memfd_secret, Backend::MemfdSecret.is_supported());
+        assert_eq!(capabilities.guard_pages, cfg!(unix));
+        assert!(capabilities.to_string().contains("memfd_secret: "));
+        assert_eq!(SecureAllocator::new().backend(), super::default_backend());
+    }
+
+    #[test]
+    fn test_secure_types() {
+        let mut vec = SecureVec::from_slice_in(b"hello", SecureAllocator::new());
+        assert!(vec.is_locked());
+        vec.resize(5000, b'!');
+        assert_eq!(&vec[..6], b"hello!");
+        assert_eq!(vec.clone(), vec);
+
+        let key = SecureBox::new_in(Box::new_in([1u64, 2, 3, 4], SecureAllocator::new()));
+        assert!(key.is_locked());
+        assert_eq!(key.unsecure(), &[1, 2, 3, 4]);
+        assert_eq!(key.clone(), key);
+
+        let string = SecureString::from_str_in("correct horse battery staple", SecureAllocator::new());
+        assert!(string.is_locked());
+        assert_eq!(string.unsecure(), "correct horse battery staple");
+        assert_eq!(string.clone(), string);
+    }
+}
@@ -0,0 +1,794 @@
+pub mod allocator;
+
+#[cfg(unix)]
+pub mod memlock {
+    extern crate libc;
+
+    use std::{
+        cell::Cell,
+        collections::BTreeMap,
+        ops::Range,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex, MutexGuard, Once, PoisonError,
+        },
+    };
+
+    use super::lock_policy::LockError;
+
+    /// The number of locked secure values that touch each page, indexed by the address of the page.
+    ///
+    /// `mlock` and `munlock` always work on whole pages, and a page may hold multiple secure values.
+    /// So a page may only be unlocked when the last secure value touching it is unlocked.
+    static LOCKED_PAGES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+    /// Lock the memory of `count` values starting at `cont` into RAM and exclude it from core dumps.
+    ///
+    /// If excluding the memory from core dumps fails, it is unlocked again.
+    /// Every successful call must be matched by a call to `munlock` with the same arguments.
+    pub fn mlock<T>(cont: *mut T, count: usize) -> Result<(), LockError> {
+        // Nothing to lock, and the pointer may be dangling
+        let Some(pages) = pages(cont, count) else {
+            return Ok(());
+        };
+        register_fork_handlers();
+        let mut locked_pages = LOCKED_PAGES.lock().unwrap_or_else(PoisonError::into_inner);
+
+        unsafe {
+            if libc::mlock(pages.start as *mut libc::c_void, pages.len()) != 0 {
+                return Err(LockError::Mlock(errno()));
+            }
+        }
+        for page in pages.clone().step_by(page_size()) {
+            *locked_pages.entry(page).or_default() += 1;
+        }
+
+        unsafe {
+            #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+            if libc::madvise(pages.start as *mut libc::c_void, pages.len(), libc::MADV_NOCORE) != 0 {
+                let errno = errno();
+                release(&mut locked_pages, pages);
+                return Err(LockError::Madvise(errno));
+            }
+            #[cfg(target_os = "linux")]
+            if libc::madvise(pages.start as *mut libc::c_void, pages.len(), libc::MADV_DONTDUMP) != 0 {
+                let errno = errno();
+                release(&mut locked_pages, pages);
+                return Err(LockError::Madvise(errno));
+            }
+        }
+        Ok(())
+    }
+
+    /// Unlock the memory of `count` values starting at `cont` that was locked with `mlock`.
+    ///
+    /// Pages that are still touched by other locked values stay locked.
+    pub fn munlock<T>(cont: *mut T, count: usize) {
+        if let Some(pages) = pages(cont, count) {
+            release(&mut LOCKED_PAGES.lock().unwrap_or_else(PoisonError::into_inner), pages);
+        }
+    }
+
+    /// Decrement the lock count of the given pages, and unlock all pages whose count drops to zero.
+    fn release(locked_pages: &mut BTreeMap<usize, usize>, pages: Range<usize>) {
+        let mut unused_start = None;
+        for page in pages.clone().step_by(page_size()) {
+            let unused = match locked_pages.get_mut(&page) {
+                Some(1) => {
+                    locked_pages.remove(&page);
+                    true
+                },
+                Some(count) => {
+                    *count -= 1;
+                    false
+                },
+                None => false,
+            };
+
+            // Unlock contiguous runs of unused pages with a single system call
+            match (unused, unused_start) {
+                (true, None) => unused_start = Some(page),
+                (false, Some(start)) => {
+                    unlock(start..page);
+                    unused_start = None;
+                },
+                _ => {},
+            }
+        }
+        if let Some(start) = unused_start {
+            unlock(start..pages.end);
+        }
+    }
+
+    fn unlock(pages: Range<usize>) {
+        unsafe {
+            let ptr = pages.start as *mut libc::c_void;
+            libc::munlock(ptr, pages.len());
+            #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+            libc::madvise(ptr, pages.len(), libc::MADV_CORE);
+            #[cfg(target_os = "linux")]
+            libc::madvise(ptr, pages.len(), libc::MADV_DODUMP);
+        }
+    }
+
+    /// A page that appears zeroed in child processes created with `fork`.
+    #[derive(Clone, Copy)]
+    struct WipedPage {
+        /// Whether the page is replaced with zeros by a `pthread_atfork` handler, because the kernel cannot wipe it,
+        /// e.g. shared mappings on Linux or any memory on platforms without `MADV_WIPEONFORK` or `minherit`.
+        by_handler: bool,
+        /// The value of `FORK_GENERATION` when the page was registered, or `usize::MAX` if it was unregistered before
+        /// the fork that created the current process.
+        generation: usize,
+        /// Whether the page is still wiped in children created by the current process.
+        ///
+        /// A page that was wiped when the current process was created stays in the map after it is unregistered, such
+        /// that `was_wiped_on_fork` still knows about it when its memory is freed.
+        registered: bool,
+    }
+
+    /// The pages that are wiped in child processes, with the page address as key.
+    static FORK_WIPED_PAGES: Mutex<BTreeMap<usize, WipedPage>> = Mutex::new(BTreeMap::new());
+
+    /// The number of forks between the first process that registered the fork handlers and the current process.
+    ///
+    /// Only pages registered in an earlier generation have actually been wiped in the current process.
+    static FORK_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+    type ForkGuard = (MutexGuard<'static, BTreeMap<usize, usize>>, MutexGuard<'static, BTreeMap<usize, WipedPage>>);
+
+    thread_local! {
+        /// Holds the page registries locked while the current thread forks, such that the child sees them in a
+        /// consistent state and can lock them itself, even if other threads of the parent were using them.
+        static FORK_GUARD: Cell<Option<ForkGuard>> = const { Cell::new(None) };
+    }
+
+    /// Let the memory of `count` values starting at `cont` appear zeroed in child processes created with `fork`, if
+    /// `wipe` is `true`, or let children inherit it again otherwise.
+    ///
+    /// Only pages that are occupied exclusively by the values are wiped, since wiping a page that is shared with other
+    /// memory, such as heap metadata, would corrupt that memory in the child. Inheriting is restored for all pages
+    /// touched by the values, which must be done before the memory is freed.
+    pub fn set_wipe_on_fork<T>(cont: *mut T, count: usize, wipe: bool) {
+        let pages = if wipe { owned_pages(cont, count) } else { pages(cont, count) };
+        let Some(pages) = pages.filter(|pages| !pages.is_empty()) else {
+            return;
+        };
+        let inherit_set = unsafe { set_inherit(pages.clone(), wipe) };
+
+        let generation = FORK_GENERATION.load(Ordering::Relaxed);
+        let mut fork_wiped_pages = FORK_WIPED_PAGES.lock().unwrap_or_else(PoisonError::into_inner);
+        for page in pages.step_by(page_size()) {
+            if wipe {
+                let wiped = WipedPage { by_handler: !inherit_set, generation, registered: true };
+                fork_wiped_pages.insert(page, wiped);
+            } else if let Some(wiped) = fork_wiped_pages.get_mut(&page).filter(|wiped| wiped.generation < generation) {
+                wiped.registered = false;
+            } else {
+                fork_wiped_pages.remove(&page);
+            }
+        }
+        drop(fork_wiped_pages);
+
+        if wipe {
+            // Also needed if the kernel wipes the pages, to find out if the process is a child
+            register_fork_handlers();
+        }
+    }
+
+    fn register_fork_handlers() {
+        static REGISTER: Once = Once::new();
+        REGISTER.call_once(|| unsafe {
+            libc::pthread_atfork(Some(prepare_fork), Some(after_fork_in_parent), Some(after_fork_in_child));
+        });
+    }
+
+    /// Returns `true` in child processes created with `fork` if the page containing `address` was wiped on the fork,
+    /// even if the page was unregistered since.
+    pub(crate) fn was_wiped_on_fork(address: usize) -> bool {
+        let generation = FORK_GENERATION.load(Ordering::Relaxed);
+        let page = address & !(page_size() - 1);
+        generation > 0
+            && FORK_WIPED_PAGES
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(&page)
+                .is_some_and(|wiped| wiped.generation < generation)
+    }
+
+    /// Let the kernel wipe `pages` in child processes or not, returns `false` if this is not supported for them.
+    unsafe fn set_inherit(pages: Range<usize>, wipe: bool) -> bool {
+        let ptr = pages.start as *mut libc::c_void;
+        #[cfg(target_os = "linux")]
+        {
+            // `MADV_KEEPONFORK` is missing from `libc`
+            const MADV_KEEPONFORK: libc::c_int = 19;
+            let advice = if wipe { libc::MADV_WIPEONFORK } else { MADV_KEEPONFORK };
+            libc::madvise(ptr, pages.len(), advice) == 0
+        }
+        #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+        {
+            // `minherit` is missing from `libc`, the constants are the same on both systems
+            extern "C" {
+                fn minherit(addr: *mut libc::c_void, len: libc::size_t, inherit: libc::c_int) -> libc::c_int;
+            }
+            const INHERIT_COPY: libc::c_int = 1;
+            const INHERIT_ZERO: libc::c_int = 3;
+            minherit(ptr, pages.len(), if wipe { INHERIT_ZERO } else { INHERIT_COPY }) == 0
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
+        {
+            let _ = (ptr, wipe);
+            false
+        }
+    }
+
+    extern "C" fn prepare_fork() {
+        // Always in this order, the registries are never locked at the same time otherwise
+        let locked_pages = LOCKED_PAGES.lock().unwrap_or_else(PoisonError::into_inner);
+        let fork_wiped_pages = FORK_WIPED_PAGES.lock().unwrap_or_else(PoisonError::into_inner);
+        FORK_GUARD.set(Some((locked_pages, fork_wiped_pages)));
+    }
+
+    extern "C" fn after_fork_in_parent() {
+        FORK_GUARD.take();
+    }
+
+    extern "C" fn after_fork_in_child() {
+        FORK_GENERATION.fetch_add(1, Ordering::Relaxed);
+        if let Some((_, mut fork_wiped_pages)) = FORK_GUARD.take() {
+            for (&page, wiped) in fork_wiped_pages.iter_mut() {
+                if !wiped.registered {
+                    // The page was inherited by this process
+                    wiped.generation = usize::MAX;
+                    continue;
+                }
+                if !wiped.by_handler {
+                    continue;
+                }
+                // Replaces the inherited page with a private page of zeros
+                unsafe {
+                    libc::mmap(
+                        page as *mut libc::c_void,
+                        page_size(),
+                        libc::PROT_READ | libc::PROT_WRITE,
+                        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED,
+                        -1,
+                        0,
+                    );
+                }
+            }
+        }
+    }
+
+    /// The whole pages that lie entirely within the memory of `count` values starting at `cont`.
+    fn owned_pages<T>(cont: *mut T, count: usize) -> Option<Range<usize>> {
+        let byte_num = count * std::mem::size_of::<T>();
+        let page_size = page_size();
+        let start = (cont as usize).next_multiple_of(page_size);
+        let end = (cont as usize + byte_num) & !(page_size - 1);
+        (start < end).then_some(start..end)
+    }
+
+    /// The whole pages touched by `count` values starting at `cont`, or `None` if the values occupy no memory.
+    fn pages<T>(cont: *mut T, count: usize) -> Option<Range<usize>> {
+        let byte_num = count * std::mem::size_of::<T>();
+        if byte_num == 0 {
+            return None;
+        }
+        let page_size = page_size();
+        let start = cont as usize & !(page_size - 1);
+        let end = (cont as usize + byte_num).next_multiple_of(page_size);
+        Some(start..end)
+    }
+
+    /// The number of locked values touching the page containing `address`.
+    #[cfg(test)]
+    pub(crate) fn lock_count(address: usize) -> usize {
+        let page = address & !(page_size() - 1);
+        LOCKED_PAGES.lock().unwrap().get(&page).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn page_size() -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+
+    fn errno() -> i32 {
+        std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::alloc::{alloc, dealloc, Layout};
+
+        use allocator_api2::boxed::Box;
+
+        use super::{lock_count, mlock, munlock, page_size, was_wiped_on_fork};
+        use crate::{Backend, ProtectedVec, SecureAllocator, SecureBox, SecureBytes, SecureString, SecureVec};
+
+        #[test]
+        fn test_shared_page() {
+            // A page of our own, such that no other test touches it
+            let layout = Layout::from_size_align(page_size(), page_size()).unwrap();
+            let page = unsafe { alloc(layout) };
+            let first = page;
+            let second = unsafe { page.add(64) };
+
+            mlock(first, 64).unwrap();
+            mlock(second, 64).unwrap();
+            assert_eq!(lock_count(page as usize), 2);
+
+            munlock(first, 64);
+            assert_eq!(lock_count(page as usize), 1);
+            munlock(second, 64);
+            assert_eq!(lock_count(page as usize), 0);
+
+            unsafe { dealloc(page, layout) };
+        }
+
+        /// Run `f` in a child process created with `fork`, and return its result.
+        ///
+        /// Other test threads may hold locks during the fork, so `f` must only read memory or drop secure values, whose
+        /// page registries are held by the forking thread during the fork.
+        fn in_child(f: impl FnOnce() -> bool) -> bool {
+            unsafe {
+                match libc::fork() {
+                    0 => libc::_exit(f() as libc::c_int),
+                    -1 => panic!("fork failed"),
+                    child => {
+                        let mut status = 0;
+                        libc::waitpid(child, &mut status, 0);
+                        libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 1
+                    },
+                }
+            }
+        }
+
+        #[test]
+        fn test_wipe_on_fork() {
+            // Large enough to occupy whole pages of the heap on its own
+            let large = SecureBytes::new(vec![0xa5; 4 * page_size()]);
+            let key = SecureBox::new_in(Box::new_in([0xa5u8; 32], SecureAllocator::new()));
+            let secret_key = SecureBox::new_in(Box::new_in([0xa5u8; 32], SecureAllocator::with_backend(Backend::MemfdSecret)));
+            let mut kept = SecureString::from_str_in("inherited", SecureAllocator::new());
+            kept.keep_on_fork();
+
+            // The partial pages at both ends of the heap allocation are shared with other memory
+            let start = large.unsecure().as_ptr() as usize;
+            let owned = start.next_multiple_of(page_size()) - start..3 * page_size();
+            assert!(in_child(|| large.unsecure()[owned.clone()].iter().all(|&byte| byte == 0)));
+            assert!(in_child(|| large.unsecure()[..owned.start].iter().all(|&byte| byte == 0xa5)));
+            assert!(in_child(|| key.unsecure() == &[0; 32]));
+            assert!(in_child(|| secret_key.unsecure() == &[0; 32]));
+            assert!(in_child(|| kept.unsecure() == "inherited"));
+            assert_eq!(large.unsecure(), vec![0xa5; 4 * page_size()]);
+
+            // Only wiped allocations may skip the canary check on drop. The forking thread holds the registry of wiped
+            // pages during the fork, so it is safe to look it up in the child.
+            let key_address = key.unsecure().as_ptr() as usize;
+            let kept_address = kept.unsecure().as_ptr() as usize;
+            assert!(!was_wiped_on_fork(key_address));
+            assert!(in_child(|| was_wiped_on_fork(key_address) && !was_wiped_on_fork(kept_address)));
+        }
+
+        #[test]
+        fn test_drop_in_child() {
+            let vec = SecureVec::from_slice_in(&[0xa5u8; 64], SecureAllocator::new());
+            let key = SecureBox::new_in(Box::new_in([0xa5u8; 32], SecureAllocator::new()));
+            let string = SecureString::from_str_in("wiped", SecureAllocator::new());
+            let protected = ProtectedVec::from_slice(&[0xa5u8; 64]);
+            let mut kept = SecureString::from_str_in("inherited", SecureAllocator::new());
+            kept.keep_on_fork();
+
+            // The canaries of the wiped allocations are zero in the child, which must not abort it
+            assert!(in_child(move || {
+                drop((vec, key, string, protected, kept));
+                true
+            }));
+        }
+
+        #[test]
+        fn test_wipe_on_fork_resize() {
+            let mut vec = SecureVec::from_slice_in(&[1u64; 16], SecureAllocator::new());
+            vec.keep_on_fork();
+            vec.resize(1024, 2);
+            assert!(in_child(|| vec.unsecure()[0] == 1 && vec.unsecure()[1023] == 2));
+        }
+
+        #[test]
+        fn test_interleaved_secrets() {
+            let mut secrets: Vec<_> = (0..1024).map(|i| SecureBytes::from(format!("secret number {i}"))).collect();
+            assert!(secrets.iter().all(SecureBytes::is_locked));
+
+            // Drop every other secret, which shares pages with its neighbours
+            let mut index = 0;
+            secrets.retain(|_| {
+                index += 1;
+                index % 2 == 0
+            });
+            for secret in &secrets {
+                let start = secret.unsecure().as_ptr() as usize;
+                assert!(lock_count(start) > 0);
+                assert!(lock_count(start + secret.unsecure().len() - 1) > 0);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub mod memlock {
+    use super::lock_policy::LockError;
+
+    pub fn mlock<T>(_cont: *mut T, count: usize) -> Result<(), LockError> {
+        if count * std::mem::size_of::<T>() == 0 {
+            Ok(())
+        } else {
+            Err(LockError::Unsupported)
+        }
+    }
+
+    pub fn munlock<T>(_cont: *mut T, _count: usize) {}
+
+    pub fn set_wipe_on_fork<T>(_cont: *mut T, _count: usize, _wipe: bool) {}
+}
+
+pub mod lock_policy {
+    use core::fmt;
+    use std::sync::atomic::{AtomicU8, Ordering};
+
+    use super::memlock;
+
+    /// The reason why memory could not be locked.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum LockError {
+        /// `mlock` failed with the contained errno, e.g. because `RLIMIT_MEMLOCK` is exhausted.
+        Mlock(i32),
+        /// `madvise` failed with the contained errno while excluding the memory from core dumps.
+        Madvise(i32),
+        /// Locking memory is not supported on this platform.
+        Unsupported,
+    }
+
+    impl LockError {
+        /// The errno of the failed system call, if any.
+        pub fn errno(&self) -> Option<i32> {
+            match self {
+                LockError::Mlock(errno) | LockError::Madvise(errno) => Some(*errno),
+                LockError::Unsupported => None,
+            }
+        }
+    }
+
+    impl fmt::Display for LockError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                LockError::Mlock(errno) => write!(f, "mlock failed: {}", std::io::Error::from_raw_os_error(*errno)),
+                LockError::Madvise(errno) => write!(f, "madvise failed: {}", std::io::Error::from_raw_os_error(*errno)),
+                LockError::Unsupported => write!(f, "locking memory is not supported on this platform"),
+            }
+        }
+    }
+
+    impl std::error::Error for LockError {}
+
+    /// What to do if the memory of a secure value cannot be locked by an infallible constructor or operation.
+    ///
+    /// Use [`is_locked`](crate::SecureVec::is_locked) on a secure value to find out if its memory is locked. Fallible
+    /// constructors like [`SecureVec::try_new`](crate::SecureVec::try_new) return the error under every policy.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[repr(u8)]
+    pub enum LockPolicy {
+        /// Continue with unlocked memory.
+        #[default]
+        Ignore,
+        /// Print a warning to stderr and continue with unlocked memory.
+        Log,
+        /// Panic.
+        Panic,
+    }
+
+    static POLICY: AtomicU8 = AtomicU8::new(LockPolicy::Ignore as u8);
+
+    /// Set the process-wide policy for handling failures to lock memory.
+    pub fn set_lock_policy(policy: LockPolicy) {
+        POLICY.store(policy as u8, Ordering::Relaxed);
+    }
+
+    /// Get the process-wide policy for handling failures to lock memory.
+    pub fn lock_policy() -> LockPolicy {
+        match POLICY.load(Ordering::Relaxed) {
+            0 => LockPolicy::Ignore,
+            1 => LockPolicy::Log,
+            _ => LockPolicy::Panic,
+        }
+    }
+
+    /// Lock memory like [`memlock::mlock`], handling failures according to the current policy.
+    ///
+    /// Returns whether the memory is locked.
+    pub(crate) fn lock<T>(cont: *mut T, count: usize) -> bool {
+        #[cfg(test)]
+        if let Some(policy) = tests::FORCED_FAILURE.get() {
+            return apply(policy, Err(LockError::Mlock(12)));
+        }
+        apply(lock_policy(), memlock::mlock(cont, count))
+    }
+
+    fn apply(policy: LockPolicy, result: Result<(), LockError>) -> bool {
+        let Err(error) = result else {
+            return true;
+        };
+
+        match policy {
+            LockPolicy::Ignore => false,
+            LockPolicy::Log => {
+                eprintln!("secure-string: continuing with unlocked memory: {error}");
+                false
+            },
+            LockPolicy::Panic => panic!("secure-string: failed to lock memory: {error}"),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{alloc::Layout, cell::Cell, panic::catch_unwind, ptr::NonNull};
+
+        use allocator_api2::{
+            alloc::{AllocError, Allocator, Global},
+            boxed::Box,
+            vec::Vec,
+        };
+
+        use super::{apply, LockError, LockPolicy};
+        use crate::{SecureArray, SecureBox, SecureVec};
+
+        thread_local! {
+            /// Makes locking fail on the current thread, with the failure handled by this policy instead of the
+            /// process-wide one, such that other tests are not affected.
+            pub(super) static FORCED_FAILURE: Cell<Option<LockPolicy>> = const { Cell::new(None) };
+            /// Whether all memory freed by `ZeroCheck` on the current thread was zeroed.
+            static FREED_ZEROED: Cell<bool> = const { Cell::new(true) };
+        }
+
+        /// Records whether memory is zeroed before it is freed.
+        struct ZeroCheck;
+
+        unsafe impl Allocator for ZeroCheck {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                let bytes = std::slice::from_raw_parts(ptr.as_ptr(), layout.size());
+                FREED_ZEROED.set(FREED_ZEROED.get() && bytes.iter().all(|&byte| byte == 0));
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        #[test]
+        fn test_apply() {
+            let error = LockError::Mlock(12);
+            for policy in [LockPolicy::Ignore, LockPolicy::Log, LockPolicy::Panic] {
+                assert!(apply(policy, Ok(())));
+            }
+            assert!(!apply(LockPolicy::Ignore, Err(error)));
+            assert!(!apply(LockPolicy::Log, Err(error)));
+        }
+
+        #[test]
+        fn test_panic_zeroes() {
+            FORCED_FAILURE.set(Some(LockPolicy::Panic));
+            let vec = catch_unwind(|| {
+                let mut secret = Vec::new_in(ZeroCheck);
+                secret.extend_from_slice(b"hunter2");
+                SecureVec::new_in(secret)
+            });
+            let boxed = catch_unwind(|| SecureBox::new_in(Box::new_in(*b"hunter2", ZeroCheck)));
+            let array = catch_unwind(|| SecureArray::new(*b"hunter2"));
+            FORCED_FAILURE.set(None);
+
+            assert!(vec.is_err() && boxed.is_err() && array.is_err());
+            // The secrets were zeroed before their memory was freed during unwinding
+            assert!(FREED_ZEROED.get());
+        }
+
+        #[test]
+        #[should_panic]
+        fn test_apply_panic() {
+            apply(LockPolicy::Panic, Err(LockError::Madvise(22)));
+        }
+
+        #[test]
+        fn test_error() {
+            assert_eq!(LockError::Mlock(12).errno(), Some(12));
+            assert_eq!(LockError::Unsupported.errno(), None);
+            assert!(LockError::Mlock(12).to_string().starts_with("mlock failed: "));
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn test_mlock_failure() {
+            // The region extends far beyond the allocation into unmapped memory
+            let mut bytes = vec![0u8; 1];
+            let error = super::memlock::mlock(bytes.as_mut_ptr(), 1 << 40).unwrap_err();
+            assert_eq!(error, LockError::Mlock(libc::ENOMEM));
+        }
+    }
+}
+
+pub mod constant_time {
+    use std::hint::black_box;
+
+    /// Reinterpret a slice of `T` as its underlying bytes.
+    ///
+    /// This reads padding bytes if `T` has any, so the result is only meaningful for types without padding.
+    pub(crate) fn as_bytes<T>(slice: &[T]) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(slice.as_ptr() as *const u8, std::mem::size_of_val(slice)) }
+    }
+
+    /// Compare the byte representations of two slices without short circuiting on the first difference.
+    ///
+    /// The running time depends only on the length of the slices, not on their contents.
+    /// Slices of different length are unequal, and this is returned immediately.
+    pub fn eq<T>(a: &[T], b: &[T]) -> bool {
+        if a.len() != b.len() {
+            return false;
+        }
+
+        let mut difference = 0u8;
+        for (x, y) in as_bytes(a).iter().zip(as_bytes(b)) {
+            // `black_box` hides the accumulator from the optimizer in every iteration, so it cannot prove that the
+            // result is decided once `difference` is non-zero and add an early exit to the loop.
+            difference = black_box(difference | (x ^ y));
+        }
+        difference == 0
+    }
+
+    /// Compare two byte slices in time that depends only on `max_length`, hiding the lengths of both slices.
+    ///
+    /// Both slices are treated as if padded with zeros to `max_length`, but are only equal if their lengths match too.
+    /// Slices longer than `max_length` are never equal to anything, and only their first `max_length` bytes are read.
+    pub fn eq_padded(a: &[u8], b: &[u8], max_length: usize) -> bool {
+        let mut difference = ((a.len() != b.len()) | (a.len() > max_length) | (b.len() > max_length)) as u8;
+        for index in 0..max_length {
+            // See `eq` for why the accumulator passes through `black_box`.
+            difference = black_box(difference | (padded_byte(a, index) ^ padded_byte(b, index)));
+        }
+        difference == 0
+    }
+
+    /// Return `slice[index]`, or zero if `index` is out of bounds, without branching on the bounds check.
+    fn padded_byte(slice: &[u8], index: usize) -> u8 {
+        let Some(last) = slice.len().checked_sub(1) else {
+            return 0;
+        };
+        let mask = ((index <= last) as u8).wrapping_neg();
+        slice[index.min(last)] & mask
+    }
+}
+
+pub mod keyed_hash {
+    use std::{
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+        sync::OnceLock,
+    };
+
+    /// Hash `bytes` with a key that is chosen randomly once per process.
+    ///
+    /// Passing only this digest to a `Hasher` ensures that the `Hasher` never sees the bytes themselves.
+    pub fn digest(bytes: &[u8]) -> u64 {
+        static KEY: OnceLock<RandomState> = OnceLock::new();
+        let mut hasher = KEY.get_or_init(RandomState::new).build_hasher();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        hint::black_box,
+        time::{Duration, Instant},
+    };
+
+    use super::{constant_time, keyed_hash};
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time::eq::<u8>(&[], &[]));
+        assert!(constant_time::eq(b"hello", b"hello"));
+        assert!(!constant_time::eq(b"hello", b"hellO"));
+        assert!(!constant_time::eq(b"hello", b"Hello"));
+        assert!(!constant_time::eq(b"hello", b"hell"));
+        assert!(constant_time::eq(&['🦄', 'a'], &['🦄', 'a']));
+        assert!(!constant_time::eq(&['🦄', 'a'], &['🦄', 'b']));
+    }
+
+    #[test]
+    fn test_constant_time_eq_padded() {
+        assert!(constant_time::eq_padded(b"", b"", 0));
+        assert!(constant_time::eq_padded(b"", b"", 16));
+        assert!(constant_time::eq_padded(b"hello", b"hello", 16));
+        assert!(constant_time::eq_padded(b"hello", b"hello", 5));
+        assert!(!constant_time::eq_padded(b"hello", b"hello", 4));
+        assert!(!constant_time::eq_padded(b"hello", b"hellO", 16));
+        assert!(!constant_time::eq_padded(b"hello", b"hell", 16));
+        assert!(!constant_time::eq_padded(b"hell", b"hell\0", 16));
+        assert!(!constant_time::eq_padded(b"", b"\0", 16));
+    }
+
+    #[test]
+    fn test_keyed_hash() {
+        assert_eq!(keyed_hash::digest(b"hello"), keyed_hash::digest(b"hello"));
+        assert_ne!(keyed_hash::digest(b"hello"), keyed_hash::digest(b"hellO"));
+        assert_ne!(keyed_hash::digest(b"hello"), keyed_hash::digest(b""));
+    }
+
+    /// The fastest of many runs is the most stable estimate of the actual cost of a comparison,
+    /// since noise from the scheduler and caches only ever makes a run slower.
+    fn fastest_comparison(compare: impl Fn() -> bool) -> Duration {
+        (0..100)
+            .map(|_| {
+                let start = Instant::now();
+                black_box(compare());
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_constant_time_eq_timing_is_independent_of_first_mismatch() {
+        const LENGTH: usize = 1 << 16;
+        let reference = vec![0xa5; LENGTH];
+        let mut first_differs = reference.clone();
+        first_differs[0] ^= 1;
+        let mut last_differs = reference.clone();
+        last_differs[LENGTH - 1] ^= 1;
+        let copy = reference.clone();
+
+        // A short circuiting comparison is orders of magnitude faster if the first byte differs.
+        // Repeat the measurements interleaved, such that a temporary slowdown affects all of them.
+        let mut first = Duration::MAX;
+        let mut last = Duration::MAX;
+        let mut equal = Duration::MAX;
+        for _ in 0..5 {
+            first = first.min(fastest_comparison(|| constant_time::eq(black_box(&reference), black_box(&first_differs))));
+            last = last.min(fastest_comparison(|| constant_time::eq(black_box(&reference), black_box(&last_differs))));
+            equal = equal.min(fastest_comparison(|| constant_time::eq(black_box(&reference), black_box(&copy))));
+        }
+
+        for (name, duration) in [("first byte differs", first), ("last byte differs", last)] {
+            assert!(
+                duration * 3 > equal && duration < equal * 3,
+                "{name} took {duration:?}, but equal slices took {equal:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_constant_time_eq_padded_timing_is_independent_of_length() {
+        const MAX_LENGTH: usize = 1 << 12;
+        let reference = vec![0xa5; MAX_LENGTH];
+        let copy = reference.clone();
+        let short = vec![0xa5; 1];
+
+        let mut different_length = Duration::MAX;
+        let mut equal = Duration::MAX;
+        for _ in 0..5 {
+            different_length = different_length.min(fastest_comparison(|| {
+                constant_time::eq_padded(black_box(&reference), black_box(&short), MAX_LENGTH)
+            }));
+            equal = equal.min(fastest_comparison(|| {
+                constant_time::eq_padded(black_box(&reference), black_box(&copy), MAX_LENGTH)
+            }));
+        }
+
+        assert!(
+            different_length * 3 > equal && different_length < equal * 3,
+            "different lengths took {different_length:?}, but equal slices took {equal:?}"
+        );
+    }
+}
@@ -1,106 +1,733 @@
+//! Serde support for the secure types, and control over how they serialize their contents.
+//!
+//! By default, the secure types serialize their contents in plaintext, e.g. to write a config file. For diagnostic
+//! dumps, the contents can be replaced with `***SECRET***` or a fingerprint:
+//!
+//! - process-wide with [`set_serialize_mode`], or for a scope on the current thread with [`with_serialize_mode`]
+//! - per field, even for types that are not secure types, with [`Redacted`] or
+//!   `#[serde(with = "secure_string::serde::redacted")]`
+//!
+//! Formats without byte strings like JSON or TOML store `SecureBytes` as an array of numbers. With the `encoding`
+//! feature, the [`hex`], [`base64`] and [`base64url`] modules serialize byte containers as strings instead.
+//!
+//! With the `serde-explicit` feature, the secure types do not implement `Serialize` unless their elements implement
+//! [`SerializableSecret`], like in the `secrecy` crate. Other fields have to opt in to serialization with
+//! `#[serde(serialize_with = "secure_string::serde::expose")]`. Deserialization works the same in both modes.
+
 use core::fmt;
-use std::{borrow::Borrow, marker::PhantomData};
+use std::{
+    any::TypeId,
+    borrow::Borrow,
+    cell::Cell,
+    marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU8, Ordering},
+};
 
+use allocator_api2::{
+    alloc::{Allocator, Global},
+    boxed::Box,
+};
 use serde::{
     de::{self, Visitor},
+    ser::SerializeTuple,
     Deserialize, Deserializer, Serialize, Serializer,
 };
+use zeroize::Zeroize;
 
-use crate::{SecureArray, SecureVec};
+#[cfg(feature = "encoding")]
+use crate::encoding::Codec;
+use crate::{
+    secure_utils::{constant_time, keyed_hash},
+    SecureArray, SecureBox, SecureBytes, SecureString, SecureVec,
+};
 
-struct BytesVisitor<Value> {
-    phandom_data: PhantomData<Value>,
+/// Serialized instead of redacted secrets.
+const REDACTED: &str = "***SECRET***";
+
+/// How the secure types serialize their contents, see [`set_serialize_mode`] and [`with_serialize_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum SerializeMode {
+    /// Serialize the contents.
+    #[default]
+    Plaintext,
+    /// Serialize the string `***SECRET***` instead of the contents.
+    Redacted,
+    /// Serialize a string like `***SECRET:5f0e1c2a9b3d4e6f***` instead of the contents, to tell whether secrets are
+    /// equal without revealing them.
+    ///
+    /// The fingerprint is keyed randomly per process, so it cannot be compared across processes or guessed offline.
+    Fingerprint,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(SerializeMode::Plaintext as u8);
+
+thread_local! {
+    static SCOPED_MODE: Cell<Option<SerializeMode>> = const { Cell::new(None) };
+}
+
+/// Set the process-wide mode of serializing secure types, which applies outside of [`with_serialize_mode`].
+pub fn set_serialize_mode(mode: SerializeMode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Get the mode of serializing secure types on the current thread.
+///
+/// This is the mode of the innermost [`with_serialize_mode`] call, or the process-wide mode outside of it.
+pub fn serialize_mode() -> SerializeMode {
+    SCOPED_MODE
+        .with(Cell::get)
+        .unwrap_or_else(|| match MODE.load(Ordering::Relaxed) {
+            0 => SerializeMode::Plaintext,
+            1 => SerializeMode::Redacted,
+            _ => SerializeMode::Fingerprint,
+        })
+}
+
+/// Call `f` with `mode` as the mode of serializing secure types on the current thread.
+///
+/// E.g. `with_serialize_mode(SerializeMode::Redacted, || serde_json::to_string(&config))` dumps a config struct with
+/// all secure types in it redacted. The previous mode is restored afterwards, also if `f` panics.
+pub fn with_serialize_mode<R>(mode: SerializeMode, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<SerializeMode>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED_MODE.with(|scoped| scoped.set(self.0));
+        }
+    }
+
+    let _restore = Restore(SCOPED_MODE.with(|scoped| scoped.replace(Some(mode))));
+    f()
+}
+
+/// Serialize a secret with `plaintext`, or redacted according to [`serialize_mode`].
+fn serialize_secret<S: Serializer>(
+    serializer: S,
+    secret: &[u8],
+    plaintext: impl FnOnce(S) -> Result<S::Ok, S::Error>,
+) -> Result<S::Ok, S::Error> {
+    match serialize_mode() {
+        SerializeMode::Plaintext => plaintext(serializer),
+        SerializeMode::Redacted => serializer.serialize_str(REDACTED),
+        SerializeMode::Fingerprint => serializer.collect_str(&format_args!("***SECRET:{:016x}***", keyed_hash::digest(secret))),
+    }
+}
+
+/// Serializes as `***SECRET***` instead of the wrapped value, and deserializes like `T`.
+///
+/// Use this for fields that should never be serialized, also if they are not secure types. Outputs `***SECRET***` in
+/// `fmt::Debug` and `fmt::Display` as well.
+#[derive(Clone, Copy, Default)]
+pub struct Redacted<T>(pub T);
+
+impl<T> Deref for Redacted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Redacted<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> From<T> for Redacted<T> {
+    fn from(value: T) -> Self {
+        Redacted(value)
+    }
+}
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
 }
 
-impl<Value> Default for BytesVisitor<Value> {
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> Serialize for Redacted<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Redacted<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize(deserializer).map(Redacted)
+    }
+}
+
+/// Serialize a field as `***SECRET***` with `#[serde(with = "secure_string::serde::redacted")]`, like [`Redacted`].
+pub mod redacted {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: ?Sized, S: Serializer>(_value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(super::REDACTED)
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        T::deserialize(deserializer)
+    }
+}
+
+/// Size hints of formats are not trusted beyond this many bytes for preallocation, like in `serde` itself.
+const MAX_PREALLOCATION: usize = 1 << 20;
+
+/// Secure types that can serialize their contents with [`expose`], also with the `serde-explicit` feature.
+pub trait Exposable {
+    /// Serialize the contents, or redact them according to [`serialize_mode`].
+    fn serialize_exposed<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer;
+}
+
+impl<T: Exposable> Exposable for Option<T> {
+    fn serialize_exposed<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Some(value) => serializer.serialize_some(&Exposed(value)),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// Serializes the contents of a secure type, see [`Exposable`].
+struct Exposed<'a, T>(&'a T);
+
+impl<'a, T: Exposable> Serialize for Exposed<'a, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0.serialize_exposed(serializer)
+    }
+}
+
+/// Serialize a field with `#[serde(serialize_with = "secure_string::serde::expose")]`, also with the `serde-explicit`
+/// feature.
+///
+/// The contents are still redacted according to [`serialize_mode`].
+pub fn expose<T: Exposable, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    value.serialize_exposed(serializer)
+}
+
+/// Marks types that may be serialized in secure types with the `serde-explicit` feature.
+///
+/// E.g. `SecureBox<Key>` implements `Serialize` if `Key` implements this trait. Without the `serde-explicit` feature,
+/// all secure types with serializable elements implement `Serialize`, and this trait has no effect.
+pub trait SerializableSecret: Serialize {}
+
+/// Implement `Serialize` for the secure types with elements that implement `$bound`.
+macro_rules! impl_serialize {
+    ($bound:ident) => {
+        impl<T, A> Serialize for SecureVec<T, A>
+        where
+            T: $bound + Copy + Zeroize + 'static,
+            A: Allocator,
+        {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                self.serialize_exposed(serializer)
+            }
+        }
+
+        impl<T, const LENGTH: usize> Serialize for SecureArray<T, LENGTH>
+        where
+            T: $bound + Copy + Zeroize + 'static,
+        {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                self.serialize_exposed(serializer)
+            }
+        }
+
+        impl<T, A> Serialize for SecureBox<T, A>
+        where
+            T: $bound + Copy,
+            A: Allocator,
+        {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                self.serialize_exposed(serializer)
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "serde-explicit"))]
+impl_serialize!(Serialize);
+
+#[cfg(feature = "serde-explicit")]
+impl_serialize!(SerializableSecret);
+
+#[cfg(not(feature = "serde-explicit"))]
+impl<A: Allocator> Serialize for SecureString<A> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.serialize_exposed(serializer)
+    }
+}
+
+/// Returns whether the elements are bytes.
+///
+/// Byte vectors and arrays are serialized as byte strings instead of sequences, which formats like CBOR store compactly.
+fn is_u8<T: 'static>() -> bool {
+    TypeId::of::<T>() == TypeId::of::<u8>()
+}
+
+/// Returns the elements as bytes if `T` is `u8`.
+fn as_bytes<T: 'static>(slice: &[T]) -> Option<&[u8]> {
+    // Safety: `T` is `u8`
+    is_u8::<T>().then(|| unsafe { std::slice::from_raw_parts(slice.as_ptr().cast(), slice.len()) })
+}
+
+/// Secure types that can be created from a [`SecureVec`], without copying the contents anywhere else.
+trait FromSecureVec<T: Copy + Zeroize>: Sized {
+    fn from_secure_vec(vec: SecureVec<T>) -> Result<Self, String>;
+}
+
+impl<T: Copy + Zeroize> FromSecureVec<T> for SecureVec<T> {
+    fn from_secure_vec(vec: SecureVec<T>) -> Result<Self, String> {
+        Ok(vec)
+    }
+}
+
+impl<T: Copy + Zeroize, const LENGTH: usize> FromSecureVec<T> for SecureArray<T, LENGTH> {
+    fn from_secure_vec(vec: SecureVec<T>) -> Result<Self, String> {
+        let content = vec
+            .unsecure()
+            .try_into()
+            .map_err(|_| format!("length mismatch: expected {LENGTH}, but got {}", vec.len()))?;
+        Ok(SecureArray::new(content))
+    }
+}
+
+/// Deserializes sequences, and byte strings if `T` is `u8`, into locked memory.
+///
+/// Borrowed bytes are copied into memory that is locked beforehand, sequences are collected into a [`SecureVec`] that
+/// grows securely, and owned buffers are locked in place, such that no copy of the secret is freed without zeroing.
+/// Buffers that the deserializer uses internally, e.g. to unescape strings, are out of our control.
+struct VecVisitor<T, Value> {
+    phandom_data: PhantomData<(T, Value)>,
+}
+
+impl<T, Value> Default for VecVisitor<T, Value> {
     fn default() -> Self {
         Self { phandom_data: Default::default() }
     }
 }
 
-impl<'de, SecureValue: TryFrom<Vec<u8>>> Visitor<'de> for BytesVisitor<SecureValue>
+impl<T, Value> VecVisitor<T, Value>
+where
+    T: Copy + Zeroize + 'static,
+    Value: FromSecureVec<T>,
+{
+    fn from_bytes<E: de::Error>(bytes: SecureBytes, source: &str) -> Result<Value, E> {
+        if !is_u8::<T>() {
+            return Err(E::invalid_type(de::Unexpected::Bytes(&[]), &"a sequence"));
+        }
+        let bytes = ManuallyDrop::new(bytes);
+        // Safety: `T` is `u8`, so this is the same type
+        let vec = unsafe { std::ptr::read(&*bytes as *const SecureBytes as *const SecureVec<T>) };
+        Value::from_secure_vec(vec).map_err(|error| E::custom(format!("cannot construct secure value from {source}: {error}")))
+    }
+}
+
+impl<'de, T, SecureValue> Visitor<'de> for VecVisitor<T, SecureValue>
 where
-    SecureValue::Error: std::fmt::Display,
+    T: Deserialize<'de> + Copy + Zeroize + 'static,
+    SecureValue: FromSecureVec<T>,
 {
     type Value = SecureValue;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a byte array or a sequence of bytes")
+        if is_u8::<T>() {
+            formatter.write_str("a byte array or a sequence of bytes")
+        } else {
+            formatter.write_str("a sequence")
+        }
     }
 
     fn visit_bytes<E>(self, value: &[u8]) -> Result<Self::Value, E>
     where
         E: de::Error,
     {
-        Self::Value::try_from(value.to_vec())
-            .map_err(|error| serde::de::Error::custom(format!("cannot construct secure value from byte slice: {error}")))
+        Self::from_bytes(SecureBytes::from_slice_in(value, Global), "byte slice")
     }
 
     fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<Self::Value, E>
     where
         E: de::Error,
     {
-        Self::Value::try_from(value)
-            .map_err(|error| serde::de::Error::custom(format!("cannot construct secure value from byte vector: {error}")))
+        Self::from_bytes(SecureBytes::new(value), "byte vector")
     }
 
     fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
     where
         A: de::SeqAccess<'de>,
     {
-        let mut value: Vec<u8> = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        let max_capacity = MAX_PREALLOCATION / std::mem::size_of::<T>().max(1);
+        let capacity = seq.size_hint().unwrap_or(0).min(max_capacity);
+        let mut value = SecureVec::new_in(allocator_api2::vec::Vec::with_capacity(capacity));
 
         while let Some(element) = seq.next_element()? {
             value.push(element);
         }
 
-        Self::Value::try_from(value)
-            .map_err(|error| serde::de::Error::custom(format!("cannot construct secure value from byte sequence: {error}")))
+        Self::Value::from_secure_vec(value)
+            .map_err(|error| serde::de::Error::custom(format!("cannot construct secure value from sequence: {error}")))
     }
 }
 
-impl<'de> Deserialize<'de> for SecureVec<u8> {
-    fn deserialize<D>(deserializer: D) -> Result<SecureVec<u8>, D::Error>
+impl<'de, T> Deserialize<'de> for SecureVec<T>
+where
+    T: Deserialize<'de> + Copy + Zeroize + 'static,
+{
+    fn deserialize<D>(deserializer: D) -> Result<SecureVec<T>, D::Error>
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_bytes(BytesVisitor::default())
+        if is_u8::<T>() {
+            deserializer.deserialize_bytes(VecVisitor::default())
+        } else {
+            deserializer.deserialize_seq(VecVisitor::default())
+        }
     }
 }
 
-impl Serialize for SecureVec<u8> {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+impl<T, A> Exposable for SecureVec<T, A>
+where
+    T: Serialize + Copy + Zeroize + 'static,
+    A: Allocator,
+{
+    fn serialize_exposed<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_bytes(self.content.borrow())
+        serialize_secret(serializer, constant_time::as_bytes(self.unsecure()), |serializer| {
+            match as_bytes(self.unsecure()) {
+                Some(bytes) => serializer.serialize_bytes(bytes),
+                None => serializer.collect_seq(self.unsecure()),
+            }
+        })
     }
 }
 
-impl<'de, const LENGTH: usize> Deserialize<'de> for SecureArray<u8, LENGTH> {
+impl<'de, T, const LENGTH: usize> Deserialize<'de> for SecureArray<T, LENGTH>
+where
+    T: Deserialize<'de> + Copy + Zeroize + 'static,
+{
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_bytes(BytesVisitor::default())
+        if is_u8::<T>() {
+            deserializer.deserialize_bytes(VecVisitor::default())
+        } else {
+            // Like arrays of the standard library
+            deserializer.deserialize_tuple(LENGTH, VecVisitor::default())
+        }
     }
 }
 
-impl<const LENGTH: usize> Serialize for SecureArray<u8, LENGTH> {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+impl<T, const LENGTH: usize> Exposable for SecureArray<T, LENGTH>
+where
+    T: Serialize + Copy + Zeroize + 'static,
+{
+    fn serialize_exposed<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_bytes(self.content.borrow())
+        serialize_secret(serializer, constant_time::as_bytes(self.unsecure()), |serializer| {
+            if let Some(bytes) = as_bytes(self.content.borrow()) {
+                return serializer.serialize_bytes(bytes);
+            }
+            let mut tuple = serializer.serialize_tuple(LENGTH)?;
+            for element in self.unsecure() {
+                tuple.serialize_element(element)?;
+            }
+            tuple.end()
+        })
     }
 }
 
+impl<T, A> Exposable for SecureBox<T, A>
+where
+    T: Serialize + Copy,
+    A: Allocator,
+{
+    fn serialize_exposed<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let secret = constant_time::as_bytes(std::slice::from_ref(self.unsecure()));
+        serialize_secret(serializer, secret, |serializer| self.unsecure().serialize(serializer))
+    }
+}
+
+impl<'de, T> Deserialize<'de> for SecureBox<T>
+where
+    T: Deserialize<'de> + Copy,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // The memory is locked before the value is moved into it, and the temporary value is zeroed afterwards
+        let mut secure = SecureBox::new_in(Box::new_uninit_in(Global));
+        let mut value = MaybeUninit::new(T::deserialize(deserializer)?);
+        *secure.unsecure_mut() = value;
+        std::slice::from_mut(&mut value).zeroize();
+        // Safety: the contents were just initialized
+        Ok(unsafe { secure.assume_init() })
+    }
+}
+
+impl<A: Allocator> Exposable for SecureString<A> {
+    fn serialize_exposed<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_secret(serializer, self.unsecure().as_bytes(), |serializer| serializer.serialize_str(self.unsecure()))
+    }
+}
+
+/// Deserializes strings into locked memory, like [`VecVisitor`].
+struct StringVisitor;
+
+impl<'de> Visitor<'de> for StringVisitor {
+    type Value = SecureString;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an utf-8 encoded string")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(SecureString::from_str_in(value, Global))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(SecureString(SecureVec::new(value.into_bytes())))
+    }
+
+    fn visit_bytes<E>(self, value: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let value = std::str::from_utf8(value).map_err(|_| E::invalid_value(de::Unexpected::Bytes(&[]), &self))?;
+        self.visit_str(value)
+    }
+
+    fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match String::from_utf8(value) {
+            Ok(value) => self.visit_string(value),
+            Err(error) => {
+                error.into_bytes().zeroize();
+                Err(E::invalid_value(de::Unexpected::Bytes(&[]), &self))
+            },
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SecureString {
+    fn deserialize<D>(deserializer: D) -> Result<SecureString, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_string(StringVisitor)
+    }
+}
+
+/// Secure byte containers that can be serialized as strings with [`hex`], [`base64`] and [`base64url`].
+#[cfg(feature = "encoding")]
+pub trait EncodableSecret: Sized {
+    /// Borrow the bytes to encode.
+    fn secret_bytes(&self) -> &[u8];
+
+    /// Create the container from the decoded bytes, or return why they do not fit.
+    fn from_decoded(bytes: SecureBytes) -> Result<Self, String>;
+}
+
+#[cfg(feature = "encoding")]
+impl EncodableSecret for SecureBytes {
+    fn secret_bytes(&self) -> &[u8] {
+        self.unsecure()
+    }
+
+    fn from_decoded(bytes: SecureBytes) -> Result<Self, String> {
+        Ok(bytes)
+    }
+}
+
+#[cfg(feature = "encoding")]
+impl<const LENGTH: usize> EncodableSecret for SecureArray<u8, LENGTH> {
+    fn secret_bytes(&self) -> &[u8] {
+        self.unsecure()
+    }
+
+    fn from_decoded(bytes: SecureBytes) -> Result<Self, String> {
+        Self::from_secure_vec(bytes)
+    }
+}
+
+/// Encodes in constant time, and serializes the encoded string or redacts it according to [`serialize_mode`].
+#[cfg(feature = "encoding")]
+fn serialize_encoded<T: EncodableSecret, S: Serializer>(value: &T, serializer: S, codec: &Codec) -> Result<S::Ok, S::Error> {
+    serialize_secret(serializer, value.secret_bytes(), |serializer| {
+        serializer.serialize_str(codec.encode(value.secret_bytes()).unsecure())
+    })
+}
+
+/// Decodes strings in constant time directly into locked memory.
+///
+/// Owned strings are zeroed after decoding.
+#[cfg(feature = "encoding")]
+struct EncodedVisitor<Value> {
+    codec: &'static Codec,
+    expecting: &'static str,
+    phandom_data: PhantomData<Value>,
+}
+
+#[cfg(feature = "encoding")]
+impl<'de, SecureValue: EncodableSecret> Visitor<'de> for EncodedVisitor<SecureValue> {
+    type Value = SecureValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.expecting)
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visit_bytes(value.as_bytes())
+    }
+
+    fn visit_string<E>(self, mut value: String) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let result = self.visit_str(&value);
+        value.zeroize();
+        result
+    }
+
+    fn visit_bytes<E>(self, value: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let bytes = self
+            .codec
+            .decode(value)
+            .map_err(|error| E::custom(format_args!("cannot decode {}: {error}", self.expecting)))?;
+        SecureValue::from_decoded(bytes).map_err(|error| E::custom(format!("cannot construct secure value: {error}")))
+    }
+}
+
+/// Define a module for `#[serde(with = ...)]` that encodes secure byte containers with `$codec`.
+#[cfg(feature = "encoding")]
+macro_rules! encoded_module {
+    ($(#[$attr:meta])* $name:ident, $codec:path, $expecting:literal) => {
+        $(#[$attr])*
+        pub mod $name {
+            use serde::{Deserializer, Serializer};
+
+            use super::{EncodableSecret, EncodedVisitor};
+
+            pub fn serialize<T: EncodableSecret, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+                super::serialize_encoded(value, serializer, &$codec)
+            }
+
+            pub fn deserialize<'de, T: EncodableSecret, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+                deserializer.deserialize_str(EncodedVisitor {
+                    codec: &$codec,
+                    expecting: $expecting,
+                    phandom_data: Default::default(),
+                })
+            }
+        }
+    };
+}
+
+#[cfg(feature = "encoding")]
+encoded_module!(
+    /// Serialize `SecureBytes` or `SecureArray<u8, N>` as a lower case hex string with
+    /// `#[serde(with = "secure_string::serde::hex")]`, encoding and decoding in constant time.
+    hex,
+    crate::encoding::HEX,
+    "a hex string"
+);
+
+#[cfg(feature = "encoding")]
+encoded_module!(
+    /// Serialize `SecureBytes` or `SecureArray<u8, N>` as a padded standard base64 string with
+    /// `#[serde(with = "secure_string::serde::base64")]`, encoding and decoding in constant time.
+    ///
+    /// The padding is optional when deserializing.
+    base64,
+    crate::encoding::BASE64,
+    "a base64 string"
+);
+
+#[cfg(feature = "encoding")]
+encoded_module!(
+    /// Serialize `SecureBytes` or `SecureArray<u8, N>` as an unpadded URL-safe base64 string with
+    /// `#[serde(with = "secure_string::serde::base64url")]`, encoding and decoding in constant time.
+    base64url,
+    crate::encoding::BASE64_URL,
+    "a base64url string"
+);
+
 #[cfg(test)]
 mod tests {
+    #[cfg(not(feature = "serde-explicit"))]
     use std::str::FromStr;
 
-    use crate::{SecureArray, SecureBytes, SecureVec};
+    use serde::{Deserialize, Serialize};
+
+    #[cfg(not(feature = "serde-explicit"))]
+    use super::{serialize_mode, Redacted};
+    use super::{with_serialize_mode, SerializeMode};
+    #[cfg(any(not(feature = "serde-explicit"), feature = "encoding"))]
+    use crate::SecureBytes;
+    use crate::{SecureArray, SecureBox, SecureString, SecureVec};
 
+    #[cfg(not(feature = "serde-explicit"))]
     #[test]
     fn test_cbor_vec() {
         let data = SecureBytes::from("hello");
@@ -110,6 +737,7 @@ mod tests {
         assert_eq!(data, deserialised);
     }
 
+    #[cfg(not(feature = "serde-explicit"))]
     #[test]
     fn test_cbor_array() {
         let data: SecureArray<_, 5> = SecureArray::from_str("hello").unwrap();
@@ -130,4 +758,186 @@ mod tests {
 
         assert_eq!(secure_bytes, secure_bytes_serde);
     }
+
+    #[cfg(not(feature = "serde-explicit"))]
+    #[test]
+    fn test_box() {
+        let key = SecureBox::new(Box::new([1u64, 2, 3, u64::MAX]));
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!(json, "[1,2,3,18446744073709551615]");
+        let deserialized: SecureBox<[u64; 4]> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, key);
+        assert!(deserialized.is_locked());
+
+        let cbor = serde_cbor::to_vec(&key).unwrap();
+        assert_eq!(serde_cbor::from_slice::<SecureBox<[u64; 4]>>(&cbor).unwrap(), key);
+        assert!(serde_json::from_str::<SecureBox<[u64; 4]>>("[1,2,3]").is_err());
+    }
+
+    #[cfg(not(feature = "serde-explicit"))]
+    #[test]
+    fn test_generic_elements() {
+        let vec = SecureVec::from(vec![1u32, 2, 70000]);
+        let json = serde_json::to_string(&vec).unwrap();
+        assert_eq!(json, "[1,2,70000]");
+        let deserialized: SecureVec<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, vec);
+        assert!(deserialized.is_locked());
+
+        let array = SecureArray::new([1i16, -2, 3]);
+        let cbor = serde_cbor::to_vec(&array).unwrap();
+        assert_eq!(cbor, b"\x83\x01\x21\x03");
+        assert_eq!(serde_cbor::from_slice::<SecureArray<i16, 3>>(&cbor).unwrap(), array);
+        assert!(serde_json::from_str::<SecureArray<i16, 3>>("[1,2]").is_err());
+        assert!(serde_json::from_str::<SecureArray<i16, 3>>("[1,2,3,4]").is_err());
+
+        // Only bytes may be deserialized from byte strings
+        let bytes = serde_cbor::to_vec(&SecureBytes::from("abcd")).unwrap();
+        assert!(serde_cbor::from_slice::<SecureVec<u32>>(&bytes).is_err());
+    }
+
+    #[cfg(not(feature = "serde-explicit"))]
+    #[derive(Serialize, Deserialize)]
+    struct Config {
+        user: String,
+        password: SecureString,
+        key: SecureBox<[u64; 2]>,
+        #[serde(with = "crate::serde::redacted")]
+        token: String,
+        note: Redacted<String>,
+    }
+
+    #[cfg(not(feature = "serde-explicit"))]
+    const CONFIG: &str = r#"{"user":"admin","password":"hunter2","key":[1,2],"token":"abc","note":"def"}"#;
+
+    #[cfg(not(feature = "serde-explicit"))]
+    #[test]
+    fn test_redacted() {
+        let config: Config = serde_json::from_str(CONFIG).unwrap();
+        assert_eq!(config.token, "abc");
+        assert_eq!(*config.note, "def");
+        assert_eq!(format!("{:?} {}", config.note, config.note), "***SECRET*** ***SECRET***");
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            r#"{"user":"admin","password":"hunter2","key":[1,2],"token":"***SECRET***","note":"***SECRET***"}"#
+        );
+
+        let json = with_serialize_mode(SerializeMode::Redacted, || serde_json::to_string(&config).unwrap());
+        assert_eq!(
+            json,
+            r#"{"user":"admin","password":"***SECRET***","key":"***SECRET***","token":"***SECRET***","note":"***SECRET***"}"#
+        );
+    }
+
+    #[cfg(not(feature = "serde-explicit"))]
+    #[test]
+    fn test_serialize_mode() {
+        let password = SecureString::from("hunter2");
+        let bytes = SecureBytes::from("hunter2");
+        let array = SecureArray::new([1u16, 2]);
+        assert_eq!(serialize_mode(), SerializeMode::Plaintext);
+
+        let fingerprints = with_serialize_mode(SerializeMode::Fingerprint, || {
+            assert_eq!(serialize_mode(), SerializeMode::Fingerprint);
+            // The innermost scope applies
+            let redacted = with_serialize_mode(SerializeMode::Redacted, || serde_json::to_string(&array).unwrap());
+            assert_eq!(redacted, r#""***SECRET***""#);
+            [&password, &SecureString::from("hunter3")].map(|secret| serde_json::to_string(secret).unwrap())
+        });
+        assert!(fingerprints[0].starts_with(r#""***SECRET:"#) && fingerprints[0].ends_with(r#"***""#));
+        assert_eq!(fingerprints[0].len(), r#""***SECRET:0123456789abcdef***""#.len());
+        assert_ne!(fingerprints[0], fingerprints[1]);
+        let fingerprint = with_serialize_mode(SerializeMode::Fingerprint, || serde_json::to_string(&bytes).unwrap());
+        assert_eq!(fingerprint, fingerprints[0]);
+
+        // Restored after panics
+        let result = std::panic::catch_unwind(|| with_serialize_mode(SerializeMode::Redacted, || panic!("while serializing")));
+        assert!(result.is_err());
+        assert_eq!(serialize_mode(), SerializeMode::Plaintext);
+        assert_eq!(serde_json::to_string(&password).unwrap(), r#""hunter2""#);
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Credentials {
+        user: String,
+        #[serde(serialize_with = "crate::serde::expose")]
+        password: SecureString,
+        #[serde(serialize_with = "crate::serde::expose")]
+        key: Option<SecureBox<[u64; 2]>>,
+        #[serde(serialize_with = "crate::serde::expose")]
+        salt: SecureArray<u8, 4>,
+    }
+
+    #[test]
+    fn test_expose() {
+        let json = r#"{"user":"admin","password":"hunter2","key":[1,2],"salt":[1,2,3,4]}"#;
+        let mut credentials: Credentials = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&credentials).unwrap(), json);
+
+        // Exposed fields are still redacted in diagnostic dumps
+        let redacted = with_serialize_mode(SerializeMode::Redacted, || serde_json::to_string(&credentials).unwrap());
+        assert_eq!(
+            redacted,
+            r#"{"user":"admin","password":"***SECRET***","key":"***SECRET***","salt":"***SECRET***"}"#
+        );
+
+        credentials.key = None;
+        assert!(serde_json::to_string(&credentials).unwrap().contains(r#""key":null"#));
+    }
+
+    #[test]
+    #[cfg(feature = "serde-explicit")]
+    fn test_serializable_secret() {
+        #[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+        struct Key([u64; 2]);
+
+        impl super::SerializableSecret for Key {}
+
+        let key = SecureBox::new(Box::new(Key([1, 2])));
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!(json, "[1,2]");
+        assert_eq!(serde_json::from_str::<SecureBox<Key>>(&json).unwrap(), key);
+    }
+
+    #[cfg(feature = "encoding")]
+    #[derive(Debug, Serialize, Deserialize)]
+    struct EncodedKeys {
+        #[serde(with = "crate::serde::hex")]
+        seed: SecureArray<u8, 4>,
+        #[serde(with = "crate::serde::base64")]
+        key: SecureBytes,
+        #[serde(with = "crate::serde::base64url")]
+        token: SecureBytes,
+    }
+
+    #[test]
+    #[cfg(feature = "encoding")]
+    fn test_encoded() {
+        use serde::de::{value::Error, IntoDeserializer};
+
+        let json = r#"{"seed":"00ff7f80","key":"+/+/","token":"-_-_"}"#;
+        let keys: EncodedKeys = serde_json::from_str(json).unwrap();
+        assert_eq!(keys.seed.unsecure(), [0x00, 0xff, 0x7f, 0x80]);
+        assert_eq!(keys.key.unsecure(), [0xfb, 0xff, 0xbf]);
+        assert_eq!(keys.token, keys.key);
+        assert!(keys.key.is_locked());
+        assert_eq!(serde_json::to_string(&keys).unwrap(), json);
+
+        let redacted = with_serialize_mode(SerializeMode::Redacted, || serde_json::to_string(&keys).unwrap());
+        assert_eq!(redacted, r#"{"seed":"***SECRET***","key":"***SECRET***","token":"***SECRET***"}"#);
+
+        // Decoding errors and lengths that do not fit
+        let error = serde_json::from_str::<EncodedKeys>(r#"{"seed":"00ff7f8g","key":"","token":""}"#).unwrap_err();
+        assert!(error.to_string().contains("cannot decode a hex string"));
+        let error = serde_json::from_str::<EncodedKeys>(r#"{"seed":"00ff7f","key":"","token":""}"#).unwrap_err();
+        assert!(error.to_string().contains("length mismatch"));
+        assert!(serde_json::from_str::<EncodedKeys>(r#"{"seed":"00ff7f80","key":"+/+/","token":"+/+/"}"#).is_err());
+
+        // Owned strings are decoded as well
+        let deserializer = IntoDeserializer::<Error>::into_deserializer(String::from("00FF7F80"));
+        let seed: SecureBytes = crate::serde::hex::deserialize(deserializer).unwrap();
+        assert_eq!(seed.unsecure(), keys.seed.unsecure());
+    }
 }
@@ -0,0 +1,153 @@
+//! Integration with the [`subtle`] crate, for constant time operations that return a [`Choice`] instead of a `bool`.
+//!
+//! `subtle::ConditionallySelectable` requires `Copy`, which the secure types cannot implement since they zero their
+//! memory on drop. Instead, `SecureArray` offers the same operations as inherent methods.
+
+use allocator_api2::alloc::Allocator;
+use subtle::{Choice, ConditionallyNegatable, ConditionallySelectable, ConstantTimeEq};
+use zeroize::Zeroize;
+
+use crate::{secure_utils::constant_time, SecureArray, SecureBox, SecureString, SecureVec};
+
+impl<A: Allocator> ConstantTimeEq for SecureVec<u8, A> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.unsecure().ct_eq(other.unsecure())
+    }
+}
+
+impl<const LENGTH: usize> ConstantTimeEq for SecureArray<u8, LENGTH> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.unsecure().ct_eq(other.unsecure())
+    }
+}
+
+impl<A: Allocator> ConstantTimeEq for SecureString<A> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.unsecure().as_bytes().ct_eq(other.unsecure().as_bytes())
+    }
+}
+
+/// Compares the byte representations of the boxed values, so the result is only meaningful if `T` has no padding bytes.
+impl<T: Copy, A: Allocator> ConstantTimeEq for SecureBox<T, A> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        let a = constant_time::as_bytes(std::slice::from_ref(self.unsecure()));
+        let b = constant_time::as_bytes(std::slice::from_ref(other.unsecure()));
+        a.ct_eq(b)
+    }
+}
+
+impl<T, const LENGTH: usize> SecureArray<T, LENGTH>
+where
+    T: ConditionallySelectable + Zeroize,
+{
+    /// Select `a` if `choice` is 0 or `b` if `choice` is 1, in constant time.
+    ///
+    /// Equivalent to [`ConditionallySelectable::conditional_select`].
+    pub fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        let mut result = a.clone();
+        result.conditional_assign(b, choice);
+        result
+    }
+
+    /// Overwrite `self` with `other` if `choice` is 1, and leave it unchanged if `choice` is 0, in constant time.
+    ///
+    /// Equivalent to [`ConditionallySelectable::conditional_assign`].
+    pub fn conditional_assign(&mut self, other: &Self, choice: Choice) {
+        for (a, b) in self.content.iter_mut().zip(other.content.iter()) {
+            a.conditional_assign(b, choice);
+        }
+    }
+
+    /// Swap `a` and `b` if `choice` is 1, and leave them unchanged if `choice` is 0, in constant time.
+    ///
+    /// Equivalent to [`ConditionallySelectable::conditional_swap`].
+    pub fn conditional_swap(a: &mut Self, b: &mut Self, choice: Choice) {
+        for (a, b) in a.content.iter_mut().zip(b.content.iter_mut()) {
+            T::conditional_swap(a, b, choice);
+        }
+    }
+}
+
+/// Negates every element.
+impl<T, const LENGTH: usize> ConditionallyNegatable for SecureArray<T, LENGTH>
+where
+    T: ConditionallyNegatable + Copy + Zeroize,
+{
+    fn conditional_negate(&mut self, choice: Choice) {
+        for element in self.content.iter_mut() {
+            element.conditional_negate(choice);
+        }
+    }
+}
+
+/// Negates every element.
+impl<T, A> ConditionallyNegatable for SecureVec<T, A>
+where
+    T: ConditionallyNegatable + Copy + Zeroize,
+    A: Allocator,
+{
+    fn conditional_negate(&mut self, choice: Choice) {
+        for element in self.content.iter_mut() {
+            element.conditional_negate(choice);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use subtle::{Choice, ConditionallyNegatable, ConstantTimeEq};
+
+    use crate::{SecureArray, SecureBox, SecureBytes, SecureString, SecureVec};
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(bool::from(SecureBytes::from("hello").ct_eq(&SecureBytes::from("hello"))));
+        assert!(!bool::from(SecureBytes::from("hello").ct_eq(&SecureBytes::from("hellO"))));
+        assert!(!bool::from(SecureBytes::from("hello").ct_eq(&SecureBytes::from("hell"))));
+
+        let array = SecureArray::<_, 5>::from_str("hello").unwrap();
+        assert!(bool::from(array.ct_eq(&SecureArray::from_str("hello").unwrap())));
+        assert!(!bool::from(array.ct_eq(&SecureArray::from_str("olleh").unwrap())));
+
+        assert!(bool::from(SecureString::from("hello").ct_eq(&SecureString::from("hello"))));
+        assert!(!bool::from(SecureString::from("hello").ct_eq(&SecureString::from("yolo"))));
+
+        let key = SecureBox::new(Box::new([1u64, 2, 3, 4]));
+        assert!(bool::from(key.ct_eq(&SecureBox::new(Box::new([1, 2, 3, 4])))));
+        assert!(!bool::from(key.ct_eq(&SecureBox::new(Box::new([1, 2, 3, 5])))));
+    }
+
+    #[test]
+    fn test_conditional_select() {
+        let mut a = SecureArray::from([1u8, 2, 3]);
+        let mut b = SecureArray::from([4u8, 5, 6]);
+        assert_eq!(SecureArray::conditional_select(&a, &b, Choice::from(0)).unsecure(), &[1, 2, 3]);
+        assert_eq!(SecureArray::conditional_select(&a, &b, Choice::from(1)).unsecure(), &[4, 5, 6]);
+
+        SecureArray::conditional_swap(&mut a, &mut b, Choice::from(0));
+        assert_eq!(a.unsecure(), &[1, 2, 3]);
+        SecureArray::conditional_swap(&mut a, &mut b, Choice::from(1));
+        assert_eq!(a.unsecure(), &[4, 5, 6]);
+        assert_eq!(b.unsecure(), &[1, 2, 3]);
+
+        a.conditional_assign(&b, Choice::from(0));
+        assert_eq!(a.unsecure(), &[4, 5, 6]);
+        a.conditional_assign(&b, Choice::from(1));
+        assert_eq!(a.unsecure(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_conditional_negate() {
+        let mut array = SecureArray::from([1i64, -2, 3]);
+        array.conditional_negate(Choice::from(0));
+        assert_eq!(array.unsecure(), &[1, -2, 3]);
+        array.conditional_negate(Choice::from(1));
+        assert_eq!(array.unsecure(), &[-1, 2, -3]);
+
+        let mut vec = SecureVec::from(vec![1i32, -2]);
+        vec.conditional_negate(Choice::from(1));
+        assert_eq!(vec.unsecure(), &[-1, 2]);
+    }
+}
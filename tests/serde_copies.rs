@@ -0,0 +1,124 @@
+//! Checks that deserializing secrets leaves no unprotected copies in freed memory.
+//!
+//! This is an integration test of its own, since it replaces the global allocator of the whole test binary.
+#![cfg(feature = "serde")]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use secure_string::{SecureArray, SecureBytes, SecureString};
+use serde::{
+    de::{value::Error, IntoDeserializer, Visitor},
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+
+const MARKER: &str = "TOP-SECRET-MARKER-17";
+
+/// Counts the freed allocations that contain [`MARKER`], on threads that enable it.
+struct TrackingAllocator;
+
+thread_local! {
+    static TRACKING: Cell<bool> = const { Cell::new(false) };
+    static LEAKS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if TRACKING.try_with(Cell::get).unwrap_or(false) {
+            let memory = std::slice::from_raw_parts(ptr, layout.size());
+            if memory.windows(MARKER.len()).any(|window| window == MARKER.as_bytes()) {
+                LEAKS.with(|leaks| leaks.set(leaks.get() + 1));
+            }
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+/// Run `f` and return how many allocations that contained the marker were freed meanwhile.
+fn leaks(f: impl FnOnce()) -> usize {
+    LEAKS.with(|leaks| leaks.set(0));
+    TRACKING.with(|tracking| tracking.set(true));
+    f();
+    TRACKING.with(|tracking| tracking.set(false));
+    LEAKS.with(Cell::get)
+}
+
+/// Passes an owned byte buffer to the visitor, like formats that deserialize into temporary buffers.
+struct ByteBufDeserializer(Vec<u8>);
+
+impl<'de> Deserializer<'de> for ByteBufDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_byte_buf(self.0)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit unit_struct
+        newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[test]
+fn test_no_unprotected_copies() {
+    // The allocator detects plain copies
+    let copy = MARKER.to_string();
+    assert_eq!(leaks(|| drop(copy)), 1);
+
+    let cbor = [&[0x40 + MARKER.len() as u8], MARKER.as_bytes()].concat();
+    let json_seq = serde_json::to_string(MARKER.as_bytes()).unwrap();
+    let json_str = serde_json::to_string(MARKER).unwrap();
+    let deserialize = || {
+        let bytes: SecureBytes = serde_cbor::from_slice(&cbor).unwrap();
+        assert_eq!(bytes.unsecure(), MARKER.as_bytes());
+        // Without a size hint, the vector has to grow
+        let bytes: SecureBytes = serde_json::from_str(&json_seq).unwrap();
+        assert_eq!(bytes.unsecure(), MARKER.as_bytes());
+        serde_json::from_str::<SecureArray<u8, 20>>(&json_seq).unwrap();
+        serde_json::from_str::<SecureArray<u8, 19>>(&json_seq).unwrap_err();
+        let string: SecureString = serde_json::from_str(&json_str).unwrap();
+        assert_eq!(string.unsecure(), MARKER);
+    };
+    assert_eq!(leaks(deserialize), 0);
+
+    // Owned buffers are taken over or zeroed
+    let bytes = MARKER.as_bytes().to_vec();
+    let array_bytes = MARKER.as_bytes().to_vec();
+    let string = MARKER.to_string();
+    let invalid = [MARKER.as_bytes(), b"\xff"].concat();
+    let deserialize = || {
+        let bytes = SecureBytes::deserialize(ByteBufDeserializer(bytes)).unwrap();
+        assert_eq!(bytes.unsecure(), MARKER.as_bytes());
+        SecureArray::<u8, 20>::deserialize(ByteBufDeserializer(array_bytes)).unwrap();
+        let string = SecureString::deserialize(IntoDeserializer::<Error>::into_deserializer(string)).unwrap();
+        assert_eq!(string.unsecure(), MARKER);
+        SecureString::deserialize(ByteBufDeserializer(invalid)).unwrap_err();
+    };
+    assert_eq!(leaks(deserialize), 0);
+}
+
+#[test]
+#[cfg(feature = "encoding")]
+fn test_encoded_no_unprotected_copies() {
+    #[derive(Deserialize)]
+    struct Token {
+        #[serde(with = "secure_string::serde::base64url")]
+        token: SecureBytes,
+    }
+
+    let json = format!(r#"{{"token":"{}"}}"#, SecureBytes::from(MARKER).to_base64url_secure().unsecure());
+    let deserialize = || {
+        let token: Token = serde_json::from_str(&json).unwrap();
+        assert_eq!(token.token.unsecure(), MARKER.as_bytes());
+    };
+    assert_eq!(leaks(deserialize), 0);
+}